@@ -0,0 +1,295 @@
+//! A tiny assembler for DWARF expressions
+//!
+//! Each function encodes a single op, concatenate them to build a program:
+//!
+//! ```
+//! use iris::asm;
+//!
+//! let prog = [asm::const1u(5), asm::const1u(3), asm::plus()].concat();
+//! assert_eq!(prog, vec![0x08, 5, 0x08, 3, 0x22]);
+//! ```
+
+/// Encode a ULEB128
+pub fn uleb(mut v: u64) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+
+        if v == 0 {
+            out.push(b);
+            return out;
+        }
+
+        out.push(b | 0x80);
+    }
+}
+
+/// Encode a SLEB128
+pub fn sleb(mut v: i64) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    loop {
+        let b = (v & 0x7f) as u8;
+        v >>= 7;
+
+        if (v == 0 && b & 0x40 == 0) || (v == -1 && b & 0x40 != 0) {
+            out.push(b);
+            return out;
+        }
+
+        out.push(b | 0x80);
+    }
+}
+
+fn op(code: u8, operands: &[u8]) -> Vec<u8> {
+    let mut out = vec![code];
+    out.extend_from_slice(operands);
+    out
+}
+
+pub fn addr(a: u64) -> Vec<u8> {
+    op(0x03, &a.to_le_bytes())
+}
+
+pub fn deref() -> Vec<u8> {
+    op(0x06, &[])
+}
+
+pub fn const1u(v: u8) -> Vec<u8> {
+    op(0x08, &[v])
+}
+
+pub fn const1s(v: i8) -> Vec<u8> {
+    op(0x09, &v.to_le_bytes())
+}
+
+pub fn const2u(v: u16) -> Vec<u8> {
+    op(0x0a, &v.to_le_bytes())
+}
+
+pub fn const2s(v: i16) -> Vec<u8> {
+    op(0x0b, &v.to_le_bytes())
+}
+
+pub fn const4u(v: u32) -> Vec<u8> {
+    op(0x0c, &v.to_le_bytes())
+}
+
+pub fn const4s(v: i32) -> Vec<u8> {
+    op(0x0d, &v.to_le_bytes())
+}
+
+pub fn const8u(v: u64) -> Vec<u8> {
+    op(0x0e, &v.to_le_bytes())
+}
+
+pub fn const8s(v: i64) -> Vec<u8> {
+    op(0x0f, &v.to_le_bytes())
+}
+
+pub fn constu(v: u64) -> Vec<u8> {
+    op(0x10, &uleb(v))
+}
+
+pub fn consts(v: i64) -> Vec<u8> {
+    op(0x11, &sleb(v))
+}
+
+pub fn dup() -> Vec<u8> {
+    op(0x12, &[])
+}
+
+pub fn drop() -> Vec<u8> {
+    op(0x13, &[])
+}
+
+pub fn over() -> Vec<u8> {
+    op(0x14, &[])
+}
+
+pub fn pick(idx: u8) -> Vec<u8> {
+    op(0x15, &[idx])
+}
+
+pub fn swap() -> Vec<u8> {
+    op(0x16, &[])
+}
+
+pub fn rot() -> Vec<u8> {
+    op(0x17, &[])
+}
+
+pub fn abs() -> Vec<u8> {
+    op(0x19, &[])
+}
+
+pub fn and() -> Vec<u8> {
+    op(0x1a, &[])
+}
+
+pub fn div() -> Vec<u8> {
+    op(0x1b, &[])
+}
+
+pub fn minus() -> Vec<u8> {
+    op(0x1c, &[])
+}
+
+/// `DW_OP_mod`
+pub fn modulo() -> Vec<u8> {
+    op(0x1d, &[])
+}
+
+pub fn mul() -> Vec<u8> {
+    op(0x1e, &[])
+}
+
+pub fn neg() -> Vec<u8> {
+    op(0x1f, &[])
+}
+
+pub fn not() -> Vec<u8> {
+    op(0x20, &[])
+}
+
+pub fn or() -> Vec<u8> {
+    op(0x21, &[])
+}
+
+pub fn plus() -> Vec<u8> {
+    op(0x22, &[])
+}
+
+pub fn plus_uconst(v: u64) -> Vec<u8> {
+    op(0x23, &uleb(v))
+}
+
+pub fn shl() -> Vec<u8> {
+    op(0x24, &[])
+}
+
+pub fn shr() -> Vec<u8> {
+    op(0x25, &[])
+}
+
+pub fn shra() -> Vec<u8> {
+    op(0x26, &[])
+}
+
+pub fn xor() -> Vec<u8> {
+    op(0x27, &[])
+}
+
+/// Branch `off` bytes from the end of this op if the top of stack is non-zero
+pub fn bra(off: i16) -> Vec<u8> {
+    op(0x28, &off.to_le_bytes())
+}
+
+pub fn eq() -> Vec<u8> {
+    op(0x29, &[])
+}
+
+pub fn ge() -> Vec<u8> {
+    op(0x2a, &[])
+}
+
+pub fn gt() -> Vec<u8> {
+    op(0x2b, &[])
+}
+
+pub fn le() -> Vec<u8> {
+    op(0x2c, &[])
+}
+
+pub fn lt() -> Vec<u8> {
+    op(0x2d, &[])
+}
+
+pub fn ne() -> Vec<u8> {
+    op(0x2e, &[])
+}
+
+/// Jump `off` bytes from the end of this op
+pub fn skip(off: i16) -> Vec<u8> {
+    op(0x2f, &off.to_le_bytes())
+}
+
+/// `DW_OP_lit0` through `DW_OP_lit31`
+pub fn lit(v: u8) -> Vec<u8> {
+    assert!(v < 32, "Bad literal ({})", v);
+
+    op(0x30 + v, &[])
+}
+
+/// `DW_OP_reg0` through `DW_OP_reg31`
+pub fn reg(r: u8) -> Vec<u8> {
+    assert!(r < 32, "Bad register ({})", r);
+
+    op(0x50 + r, &[])
+}
+
+/// `DW_OP_breg0` through `DW_OP_breg31`
+pub fn breg(r: u8, off: i64) -> Vec<u8> {
+    assert!(r < 32, "Bad register ({})", r);
+
+    op(0x70 + r, &sleb(off))
+}
+
+pub fn regx(r: u64) -> Vec<u8> {
+    op(0x90, &uleb(r))
+}
+
+pub fn bregx(r: u64, off: i64) -> Vec<u8> {
+    op(0x92, &[uleb(r), sleb(off)].concat())
+}
+
+pub fn deref_size(sz: u8) -> Vec<u8> {
+    op(0x94, &[sz])
+}
+
+pub fn nop() -> Vec<u8> {
+    op(0x96, &[])
+}
+
+pub fn form_tls_address() -> Vec<u8> {
+    op(0x9b, &[])
+}
+
+pub fn piece(size: u64) -> Vec<u8> {
+    op(0x93, &uleb(size))
+}
+
+pub fn bit_piece(size: u64, offset: u64) -> Vec<u8> {
+    op(0x9d, &[uleb(size), uleb(offset)].concat())
+}
+
+pub fn stack_value() -> Vec<u8> {
+    op(0x9f, &[])
+}
+
+pub fn implicit_value(v: &[u8]) -> Vec<u8> {
+    op(0x9e, &[uleb(v.len() as u64), v.to_vec()].concat())
+}
+
+/// `DW_OP_const_type`, `die` is the offset of the base type DIE
+pub fn const_type(die: u64, v: &[u8]) -> Vec<u8> {
+    assert!(v.len() < 256, "Bad constant size ({})", v.len());
+
+    op(0xa4, &[uleb(die), vec![v.len() as u8], v.to_vec()].concat())
+}
+
+/// `DW_OP_convert`, `die` is the offset of the base type DIE, or 0 for the
+/// generic type
+pub fn convert(die: u64) -> Vec<u8> {
+    op(0xa8, &uleb(die))
+}
+
+pub fn call2(die: u16) -> Vec<u8> {
+    op(0x98, &die.to_le_bytes())
+}
+
+pub fn call4(die: u32) -> Vec<u8> {
+    op(0x99, &die.to_le_bytes())
+}
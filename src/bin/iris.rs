@@ -0,0 +1,70 @@
+//! Run a DWARF expression from a core, printing each step and the result
+//!
+//! Usage: `iris <core> <pc> <ctx> [limit]`, numbers may be given in hex with
+//! a `0x` prefix. Set `RUST_LOG` for the VM's own logging.
+
+use std::error::Error;
+use std::process;
+
+use iris::DwarfVm;
+
+/// The instruction limit if none is given
+const DEFAULT_LIMIT: usize = 10_000;
+
+fn parse_num(s: &str) -> Result<u64, Box<dyn Error>> {
+    let v = match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16)?,
+        None => s.parse()?,
+    };
+
+    Ok(v)
+}
+
+fn run(argv: &[String]) -> Result<(), Box<dyn Error>> {
+    if argv.len() < 4 || argv.len() > 5 {
+        return Err(format!("usage: {} <core> <pc> <ctx> [limit]", argv[0]).into());
+    }
+
+    let core = std::fs::read(&argv[1])?;
+    let pc = parse_num(&argv[2])?;
+    let ctx = parse_num(&argv[3])?;
+    let limit = match argv.get(4) {
+        Some(limit) => parse_num(limit)? as usize,
+        None => DEFAULT_LIMIT,
+    };
+
+    let mut dvm = DwarfVm::new_checked(pc, ctx, &core)?;
+
+    for _ in 0..limit {
+        let event = match dvm.step_event() {
+            Ok(event) => event,
+            Err(e) => {
+                println!("stopped at {:#x}: {}", dvm.pc, e);
+                break;
+            }
+        };
+
+        println!(
+            "{:#x}: {:<24} pop {:x?} push {:x?}",
+            event.pc, event.op, event.popped, event.pushed
+        );
+    }
+
+    match dvm.result() {
+        Some(r) => println!("result: {:x?}", r),
+        None => println!("no result"),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+
+    let argv: Vec<String> = std::env::args().collect();
+
+    if let Err(e) = run(&argv) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,96 @@
+//! Compressed debug section support
+
+use std::borrow::Cow;
+use std::convert::TryInto;
+
+use crate::DwarfVmError;
+
+pub(crate) const SHF_COMPRESSED: u64 = 0x800;
+
+// sizeof(Elf64_Chdr)
+const CHDR_SIZE: usize = 24;
+
+/// Get the contents of the section `name`, decompressing it if needed
+///
+/// `lookup` finds a section's flags and raw contents by name. Both
+/// `SHF_COMPRESSED` sections and the older GNU `.zdebug_*` sections are
+/// handled. Decompression requires the `compressed` feature.
+pub(crate) fn section_data<'a, F>(
+    lookup: F,
+    name: &'static str,
+) -> Result<Option<Cow<'a, [u8]>>, DwarfVmError>
+where
+    F: Fn(&str) -> Option<(u64, &'a [u8])>,
+{
+    if let Some((flags, data)) = lookup(name) {
+        if flags & SHF_COMPRESSED == 0 {
+            return Ok(Some(Cow::Borrowed(data)));
+        }
+
+        return elf_chdr(data)
+            .map(|x| Some(Cow::Owned(x)))
+            .ok_or(DwarfVmError::BadCompressedSection(name));
+    }
+
+    let zname = format!(".z{}", &name[1..]);
+
+    if let Some((_, data)) = lookup(&zname) {
+        return gnu_zdebug(data)
+            .map(|x| Some(Cow::Owned(x)))
+            .ok_or(DwarfVmError::BadCompressedSection(name));
+    }
+
+    Ok(None)
+}
+
+/// Decompress a section prefixed with an `Elf64_Chdr`
+fn elf_chdr(data: &[u8]) -> Option<Vec<u8>> {
+    let ty = u32::from_le_bytes(data.get(0..4)?.try_into().unwrap());
+    let size = u64::from_le_bytes(data.get(8..16)?.try_into().unwrap());
+
+    inflate(ty, data.get(CHDR_SIZE..)?, size)
+}
+
+/// Decompress a `.zdebug_*` section, a `ZLIB` magic then a big endian size
+fn gnu_zdebug(data: &[u8]) -> Option<Vec<u8>> {
+    if data.get(0..4)? != b"ZLIB" {
+        return None;
+    }
+
+    let size = u64::from_be_bytes(data.get(4..12)?.try_into().unwrap());
+
+    // ELFCOMPRESS_ZLIB
+    inflate(1, data.get(12..)?, size)
+}
+
+/// Decompress `data`, which must expand to exactly `size` bytes
+///
+/// `size` comes from the core, so it is only used as a limit, the output
+/// grows as data is actually produced.
+#[cfg(feature = "compressed")]
+fn inflate(ty: u32, data: &[u8], size: u64) -> Option<Vec<u8>> {
+    use std::io::Read;
+
+    const ELFCOMPRESS_ZLIB: u32 = 1;
+    const ELFCOMPRESS_ZSTD: u32 = 2;
+
+    let reader: Box<dyn Read + '_> = match ty {
+        ELFCOMPRESS_ZLIB => Box::new(flate2::read::ZlibDecoder::new(data)),
+        ELFCOMPRESS_ZSTD => Box::new(zstd::stream::read::Decoder::with_buffer(data).ok()?),
+        _ => return None,
+    };
+
+    // read one byte past the size so overlong data is caught
+    let mut out = Vec::new();
+    reader
+        .take(size.saturating_add(1))
+        .read_to_end(&mut out)
+        .ok()?;
+
+    Some(out).filter(|x| x.len() as u64 == size)
+}
+
+#[cfg(not(feature = "compressed"))]
+fn inflate(_ty: u32, _data: &[u8], _size: u64) -> Option<Vec<u8>> {
+    None
+}
@@ -0,0 +1,215 @@
+//! Decoding for ops `dwarf_dis` does not know about
+
+use std::fmt;
+
+const DW_OP_PIECE: u8 = 0x93;
+const DW_OP_CALL2: u8 = 0x98;
+const DW_OP_CALL4: u8 = 0x99;
+const DW_OP_FORM_TLS_ADDRESS: u8 = 0x9b;
+const DW_OP_BIT_PIECE: u8 = 0x9d;
+const DW_OP_IMPLICIT_VALUE: u8 = 0x9e;
+const DW_OP_STACK_VALUE: u8 = 0x9f;
+const DW_OP_ADDRX: u8 = 0xa1;
+const DW_OP_CONSTX: u8 = 0xa2;
+const DW_OP_ENTRY_VALUE: u8 = 0xa3;
+const DW_OP_CONST_TYPE: u8 = 0xa4;
+const DW_OP_CONVERT: u8 = 0xa8;
+const DW_OP_GNU_PUSH_TLS_ADDRESS: u8 = 0xe0;
+const DW_OP_GNU_ENTRY_VALUE: u8 = 0xf3;
+const DW_OP_GNU_CONST_TYPE: u8 = 0xf4;
+const DW_OP_GNU_CONVERT: u8 = 0xf7;
+const DW_OP_GNU_ADDR_INDEX: u8 = 0xfb;
+const DW_OP_GNU_CONST_INDEX: u8 = 0xfc;
+
+/// The longest op without a block operand, an opcode and two ULEB128s
+/// of up to 10 bytes each, e.g. `DW_OP_bregx` or `DW_OP_bit_piece`
+#[cfg(feature = "linux-live")]
+pub(crate) const MAX_OP_LEN: u64 = 21;
+
+/// An op decoded by iris itself, as `dwarf_dis` has no support for it
+///
+/// GNU extension ops are decoded as their standard equivalents. As these ops
+/// can't be represented as an `Op`, breakpoints on them are passed `Op::Nop`.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ExtOp {
+    /// `DW_OP_implicit_value`, the block is the value itself
+    ImplicitValue(Vec<u8>),
+    /// `DW_OP_stack_value`, the top of the stack is the value, not its address
+    StackValue,
+    /// `DW_OP_piece`, the preceding location holds the next `size` bytes
+    Piece(u64),
+    /// `DW_OP_bit_piece`, the preceding location holds the next `size` bits,
+    /// starting `offset` bits into it
+    BitPiece { size: u64, offset: u64 },
+    /// `DW_OP_call2` or `DW_OP_call4`, run the expression of the DIE at offset
+    /// `die`, see `DwarfVm::add_call_target`
+    Call(u64),
+    /// `DW_OP_form_tls_address`, turn a TLS offset into an address
+    FormTlsAddress,
+    /// `DW_OP_entry_value`, the value of the block on entry to the function
+    ///
+    /// This needs the caller's frame, so is not supported.
+    EntryValue(Vec<u8>),
+    /// `DW_OP_addrx`, an index into `.debug_addr`, not supported
+    Addrx(u64),
+    /// `DW_OP_constx`, an index into `.debug_addr`, not supported
+    Constx(u64),
+    /// `DW_OP_const_type`, a constant of the type at DIE offset `die`
+    ConstType { die: u64, value: Vec<u8> },
+    /// `DW_OP_convert`, convert the top of the stack to the type at DIE
+    /// offset `die`, or the generic type if `die` is 0, see
+    /// `DwarfVm::add_base_type`
+    Convert(u64),
+}
+
+impl fmt::Display for ExtOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExtOp::Call(die) => write!(f, "call <{:#x}>", die),
+            ExtOp::FormTlsAddress => write!(f, "form_tls_address"),
+            ExtOp::EntryValue(v) => write!(f, "entry_value {:02x?}", v),
+            ExtOp::Addrx(idx) => write!(f, "addrx {}", idx),
+            ExtOp::Constx(idx) => write!(f, "constx {}", idx),
+            ExtOp::ImplicitValue(v) => write!(f, "implicit_value {:02x?}", v),
+            ExtOp::StackValue => write!(f, "stack_value"),
+            ExtOp::Piece(size) => write!(f, "piece {}", size),
+            ExtOp::BitPiece { size, offset } => write!(f, "bit_piece {} {}", size, offset),
+            ExtOp::ConstType { die, value } => {
+                write!(f, "const_type <{:#x}> {:02x?}", die, value)
+            }
+            ExtOp::Convert(die) => write!(f, "convert <{:#x}>", die),
+        }
+    }
+}
+
+/// Read a ULEB128, returning the value and its encoded length
+pub(crate) fn read_uleb(data: &[u8]) -> Option<(u64, usize)> {
+    let mut v = 0;
+    let mut shift = 0;
+
+    for (ii, b) in data.iter().enumerate() {
+        if shift < 64 {
+            v |= ((b & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+
+        if b & 0x80 == 0 {
+            return Some((v, ii + 1));
+        }
+    }
+
+    None
+}
+
+fn read_block(data: &[u8]) -> Option<(&[u8], usize)> {
+    let (len, off) = read_uleb(data)?;
+    let end = off.checked_add(len as usize)?;
+
+    Some((data.get(off..end)?, end))
+}
+
+/// The encoded length of the op at the start of `data`, if it has a block
+/// operand and so may be longer than `MAX_OP_LEN`
+///
+/// Returns `None` for other ops, or if the block's length isn't readable.
+#[cfg(feature = "linux-live")]
+pub(crate) fn block_op_len(data: &[u8]) -> Option<u64> {
+    let operands = data.get(1..)?;
+
+    let len = match *data.first()? {
+        DW_OP_IMPLICIT_VALUE | DW_OP_ENTRY_VALUE | DW_OP_GNU_ENTRY_VALUE => {
+            let (len, off) = read_uleb(operands)?;
+
+            (off as u64).checked_add(len)?
+        }
+        DW_OP_CONST_TYPE | DW_OP_GNU_CONST_TYPE => {
+            let (_, off) = read_uleb(operands)?;
+
+            off as u64 + 1 + *operands.get(off)? as u64
+        }
+        _ => return None,
+    };
+
+    len.checked_add(1)
+}
+
+/// Decode an extended op
+///
+/// Returns `Ok(None)` if the opcode is one `dwarf_dis` handles, and `Err` if
+/// the operands are truncated.
+pub(crate) fn decode(data: &[u8]) -> Result<Option<(usize, ExtOp)>, ()> {
+    let operands = data.get(1..).ok_or(())?;
+
+    let (sz, op) = match data[0] {
+        DW_OP_FORM_TLS_ADDRESS | DW_OP_GNU_PUSH_TLS_ADDRESS => (0, ExtOp::FormTlsAddress),
+        DW_OP_ENTRY_VALUE | DW_OP_GNU_ENTRY_VALUE => {
+            let (block, sz) = read_block(operands).ok_or(())?;
+
+            (sz, ExtOp::EntryValue(block.to_vec()))
+        }
+        DW_OP_ADDRX | DW_OP_GNU_ADDR_INDEX => {
+            let (idx, sz) = read_uleb(operands).ok_or(())?;
+
+            (sz, ExtOp::Addrx(idx))
+        }
+        DW_OP_CONSTX | DW_OP_GNU_CONST_INDEX => {
+            let (idx, sz) = read_uleb(operands).ok_or(())?;
+
+            (sz, ExtOp::Constx(idx))
+        }
+        DW_OP_IMPLICIT_VALUE => {
+            let (block, sz) = read_block(operands).ok_or(())?;
+
+            (sz, ExtOp::ImplicitValue(block.to_vec()))
+        }
+        DW_OP_STACK_VALUE => (0, ExtOp::StackValue),
+        DW_OP_CALL2 => {
+            let die = operands.get(..2).ok_or(())?;
+
+            (2, ExtOp::Call(u16::from_le_bytes([die[0], die[1]]) as u64))
+        }
+        DW_OP_CALL4 => {
+            let die = operands.get(..4).ok_or(())?;
+
+            (
+                4,
+                ExtOp::Call(u32::from_le_bytes([die[0], die[1], die[2], die[3]]) as u64),
+            )
+        }
+        DW_OP_PIECE => {
+            let (size, sz) = read_uleb(operands).ok_or(())?;
+
+            (sz, ExtOp::Piece(size))
+        }
+        DW_OP_BIT_PIECE => {
+            let (size, off) = read_uleb(operands).ok_or(())?;
+            let (offset, sz) = read_uleb(&operands[off..]).ok_or(())?;
+
+            (off + sz, ExtOp::BitPiece { size, offset })
+        }
+        DW_OP_CONST_TYPE | DW_OP_GNU_CONST_TYPE => {
+            let (die, off) = read_uleb(operands).ok_or(())?;
+
+            // unlike implicit_value the size is a single byte
+            let len = *operands.get(off).ok_or(())? as usize;
+            let value = operands.get(off + 1..off + 1 + len).ok_or(())?;
+
+            (
+                off + 1 + len,
+                ExtOp::ConstType {
+                    die,
+                    value: value.to_vec(),
+                },
+            )
+        }
+        DW_OP_CONVERT | DW_OP_GNU_CONVERT => {
+            let (die, sz) = read_uleb(operands).ok_or(())?;
+
+            (sz, ExtOp::Convert(die))
+        }
+        _ => return Ok(None),
+    };
+
+    Ok(Some((sz + 1, op)))
+}
@@ -2,34 +2,957 @@ use std::collections::BTreeMap;
 use std::convert::TryInto;
 use std::error::Error;
 use std::fmt;
+use std::io::{self, Read, Write};
+use std::ops::ControlFlow;
+use std::sync::Arc;
 
 use log::*;
+use xmas_elf::program;
+use xmas_elf::sections::{SectionHeader, ShType};
 use xmas_elf::ElfFile;
 
 use dwarf_dis::{decode, Op};
 
+pub mod asm;
+mod compress;
+mod ext;
+#[cfg(feature = "linux-live")]
+mod live;
+mod loclist;
+mod notes;
+mod snapshot;
+
+pub use ext::ExtOp;
+#[cfg(feature = "linux-live")]
+pub use live::ProcMem;
+pub use notes::ThreadRegs;
+
+/// The address `load_location_list` places expressions at
+pub const EXPR_BASE: u64 = 0xffff_0000_0000_0000;
+
 /// A DwarfVm state snapshot
-#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+///
+/// States order by PC, then lexicographically by stack from the bottom.
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq, Ord, PartialOrd)]
 pub struct DwarfVmState {
     pc: u64,
     stack: Vec<u64>,
 }
 
+impl DwarfVmState {
+    /// Create a new state snapshot
+    pub fn new(pc: u64, stack: Vec<u64>) -> Self {
+        Self { pc, stack }
+    }
+
+    /// The PC of this snapshot
+    pub fn pc(&self) -> u64 {
+        self.pc
+    }
+
+    /// The stack of this snapshot, top of stack last
+    pub fn stack(&self) -> &[u64] {
+        &self.stack
+    }
+
+    /// Compare against another state, returning the first difference
+    ///
+    /// `self` is treated as the expected state and `other` as the actual one.
+    pub fn diff(&self, other: &DwarfVmState) -> Option<StateDiff> {
+        if self.pc != other.pc {
+            return Some(StateDiff::Pc {
+                expected: self.pc,
+                actual: other.pc,
+            });
+        }
+
+        if self.stack.len() != other.stack.len() {
+            return Some(StateDiff::StackLen {
+                expected: self.stack.len(),
+                actual: other.stack.len(),
+            });
+        }
+
+        self.stack
+            .iter()
+            .zip(&other.stack)
+            .enumerate()
+            .find(|(_, (e, a))| e != a)
+            .map(|(idx, (e, a))| StateDiff::Stack {
+                idx,
+                expected: *e,
+                actual: *a,
+            })
+    }
+}
+
+/// The result of evaluating an expression
 #[derive(Clone, Debug, Hash, Eq, PartialEq)]
-pub enum DwarfVmError {
-    Decode,
+pub enum ExprResult {
+    /// The top of the stack, the address of the value
+    Address(u64),
+    /// The block of a `DW_OP_implicit_value`, the value itself
+    ///
+    /// The bytes are in target order, it is up to the caller to interpret them.
+    ImplicitBytes(Vec<u8>),
+    /// The top of the stack after a `DW_OP_stack_value`, the value itself
+    Value(u64),
+    /// The value lives in this register, the expression was just a
+    /// `DW_OP_reg` or `DW_OP_regx`
+    Register(u64),
+}
+
+/// What kind of result the caller expects, see `DwarfVm::finalize`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum ExpectedResult {
+    /// The location of the value, `ExprResult::Address` or
+    /// `ExprResult::Register`
+    Address,
+    /// The value itself, `ExprResult::Value` or `ExprResult::ImplicitBytes`
+    Value,
+}
+
+/// How `DwarfVm::evaluate` runs an expression
+#[derive(Clone, Copy, Debug, Default, Hash, Eq, PartialEq)]
+pub struct EvalConfig {
+    /// The maximum number of instructions to execute, or `None` for no limit
+    pub limit: Option<usize>,
+    /// The kind of result the caller expects, checked as by
+    /// `DwarfVm::finalize`. Composite locations satisfy either kind.
+    pub expect: Option<ExpectedResult>,
+}
+
+/// The fully typed result of an expression, see `DwarfVm::evaluate`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum Evaluation {
+    /// The value itself, from `DW_OP_stack_value`
+    Value(u64),
+    /// The value lives in memory at this address
+    Address(u64),
+    /// The value lives in this register
+    Register(u64),
+    /// The value itself, from `DW_OP_implicit_value`
+    ImplicitBytes(Vec<u8>),
+    /// The value is split into pieces, from `DW_OP_piece` or
+    /// `DW_OP_bit_piece`
+    Composite(Vec<Piece>),
+}
+
+impl From<ExprResult> for Evaluation {
+    fn from(r: ExprResult) -> Self {
+        match r {
+            ExprResult::Address(a) => Evaluation::Address(a),
+            ExprResult::ImplicitBytes(v) => Evaluation::ImplicitBytes(v),
+            ExprResult::Value(v) => Evaluation::Value(v),
+            ExprResult::Register(r) => Evaluation::Register(r),
+        }
+    }
+}
+
+/// Where a piece of a composite location lives, see `DwarfVm::pieces`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum PieceLocation {
+    /// In target memory at this address
+    Memory(u64),
+    /// The value itself, from `DW_OP_stack_value`
+    Value(u64),
+    /// The value itself, from `DW_OP_implicit_value`
+    Implicit(Vec<u8>),
+    /// In a register, from `DW_OP_reg` or `DW_OP_regx`, along with its value
+    Register { reg: u64, value: u64 },
+    /// Nowhere, the piece was optimized out
+    Empty,
+}
+
+/// One piece of a composite location, built by `DW_OP_piece` and
+/// `DW_OP_bit_piece`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct Piece {
+    /// The size of the piece in bits
+    pub size: u64,
+    /// Where the piece starts within `location`, in bits
+    pub offset: u64,
+    pub location: PieceLocation,
+}
+
+/// A memory read performed by an op
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct MemRead {
+    pub addr: u64,
+    pub size: u8,
+    pub value: u64,
+    /// Some or all of the bytes weren't mapped, and were made up by the
+    /// unmapped fill or the short read policy
+    pub synthetic: bool,
+}
+
+/// Where the bytes of a read came from, see `DwarfVm::read_with_source`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum ReadSource {
+    Overlay,
+    /// A region added with `DwarfVm::map_borrowed`
+    Mapped,
+    /// A running process, see `DwarfVm::set_live_memory`
+    Live,
+    /// A section of the core, by name
+    Section(String),
+    /// A `PT_LOAD` segment of the core with no covering section
+    Segment,
+    /// The unmapped fill, see `DwarfVm::set_unmapped_fill`
+    Poison,
+}
+
+/// Where a register's value came from, see `DwarfVm::effective_reg`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum RegSource {
+    /// The register provider, see `DwarfVm::set_register_provider`
+    Provider,
+    /// The context structure in memory, see `DwarfVm::set_ctx`
+    Context,
+}
+
+/// What to do when a read runs off the end of the region it starts in
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum ShortReadPolicy {
+    /// Fail with `DwarfVmError::Unmapped`, unless an unmapped fill is set
+    Error,
+    /// Use the bytes available, zero extended
+    ZeroExtend,
+}
+
+impl Default for ShortReadPolicy {
+    fn default() -> Self {
+        ShortReadPolicy::Error
+    }
+}
+
+/// How comparison ops represent true, see `DwarfVm::set_bool_repr`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum BoolRepr {
+    /// True is 1, as the DWARF spec requires
+    OneZero,
+    /// True is all ones in the generic type
+    AllOnes,
+}
+
+impl Default for BoolRepr {
+    fn default() -> Self {
+        BoolRepr::OneZero
+    }
+}
+
+/// The gas charged for each kind of op, see `DwarfVm::set_gas_costs`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub struct GasTable {
+    /// Literals, constants and `DW_OP_addr`
+    pub constant: u64,
+    /// Stack manipulation, such as `DW_OP_dup` and `DW_OP_pick`
+    pub stack: u64,
+    /// Arithmetic, logic and comparisons
+    pub arithmetic: u64,
+    /// Branches, skips and `DW_OP_nop`
+    pub control: u64,
+    /// Register reads, which read the context structure
+    pub register: u64,
+    /// Memory reads, `DW_OP_deref` and `DW_OP_deref_size`
+    pub memory: u64,
+    /// Extended ops, see `ExtOp`
+    pub ext: u64,
+}
+
+impl Default for GasTable {
+    fn default() -> Self {
+        GasTable {
+            constant: 1,
+            stack: 1,
+            arithmetic: 1,
+            control: 1,
+            register: 2,
+            memory: 4,
+            ext: 1,
+        }
+    }
+}
+
+impl GasTable {
+    /// The gas charged for `op`
+    fn cost(&self, op: &Op) -> u64 {
+        match op {
+            Op::Deref | Op::DerefSize(_) => self.memory,
+            Op::Addr(_)
+            | Op::Const1u(_)
+            | Op::Const1s(_)
+            | Op::Const2u(_)
+            | Op::Const2s(_)
+            | Op::Const4u(_)
+            | Op::Const4s(_)
+            | Op::Const8u(_)
+            | Op::Constu(_)
+            | Op::Const8s(_)
+            | Op::Consts(_)
+            | Op::Lit(_) => self.constant,
+            Op::Reg(_) | Op::RegX(_) | Op::BReg(_, _) | Op::BRegX(_, _) => self.register,
+            Op::Dup | Op::Over | Op::Pick(_) | Op::Drop | Op::Swap | Op::Rot => self.stack,
+            Op::Abs
+            | Op::Neg
+            | Op::Not
+            | Op::PlusConst(_)
+            | Op::And
+            | Op::Div
+            | Op::Minus
+            | Op::Mod
+            | Op::Mul
+            | Op::Or
+            | Op::Plus
+            | Op::Shl
+            | Op::Shr
+            | Op::Shra
+            | Op::Xor
+            | Op::Eq
+            | Op::Ge
+            | Op::Gt
+            | Op::Le
+            | Op::Lt
+            | Op::Ne => self.arithmetic,
+            Op::Nop | Op::Bra(_) | Op::Skip(_) => self.control,
+        }
+    }
+}
+
+/// Everything a single step did, see `DwarfVm::step_event`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct StepEvent {
+    /// The PC of the op executed
+    pub pc: u64,
+    /// The op executed, rendered as text
+    pub op: String,
+    pub reads: Vec<MemRead>,
+    /// Values popped, in the order they were popped
+    pub popped: Vec<u64>,
+    /// Values pushed, in the order they were pushed
+    pub pushed: Vec<u64>,
+    /// The PC after the op executed
+    pub next_pc: u64,
+}
+
+/// Why `DwarfVm::run_summary` stopped
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum StopReason {
+    /// The instruction limit was reached
+    Limit,
+    /// A breakpoint bailed
     Breakpoint,
+    /// The PC left mapped memory, e.g. the end of a loaded expression
+    ProgramEnd,
+    Error(DwarfVmError),
+}
+
+/// Statistics for a single `DwarfVm::run_summary` call
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct RunSummary {
+    pub instructions: usize,
+    pub reads: u64,
+    /// The deepest the stack got during the run
+    pub max_stack: usize,
+    pub stop_reason: StopReason,
+}
+
+/// Whether the VM can run an op, see `DwarfVm::capability_report`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum Capability {
+    Supported,
+    /// The op is not implemented, see `DwarfVm::set_skip_unknown`
+    Unimplemented,
+    /// The op needs the named configuration before it can run
+    RequiresConfig(&'static str),
+}
+
+/// The capability of each op in a range, see `DwarfVm::capability_report`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct CapabilityReport {
+    /// The PC of each op along with its capability
+    pub ops: Vec<(u64, Capability)>,
+}
+
+impl CapabilityReport {
+    /// Can every op run as the VM is configured
+    pub fn runnable(&self) -> bool {
+        self.ops.iter().all(|(_, x)| *x == Capability::Supported)
+    }
+}
+
+/// An expression decoded ahead of time, see `DwarfVm::compile`
+pub struct CompiledExpr {
+    start: u64,
+    end: u64,
+    /// The PC, size and op of each op, sorted by PC
+    ops: Vec<(u64, u64, Op)>,
+}
+
+impl CompiledExpr {
+    /// The range of PCs the expression was compiled from
+    pub fn range(&self) -> std::ops::Range<u64> {
+        self.start..self.end
+    }
+}
+
+/// A single push or pop, see `DwarfVm::set_stack_observer`
+#[derive(Clone, Copy, Debug, Hash, Eq, PartialEq)]
+pub enum StackEvent {
+    Push(u64),
+    Pop(u64),
+}
+
+/// Side effects recorded while stepping
+enum VmEvent {
+    Push(u64),
+    Pop(u64),
+    Read(MemRead),
+    Implicit(Vec<u8>),
+}
+
+/// A DwarfVm memory overlay snapshot
+#[derive(Clone, Debug, Default, Hash, Eq, PartialEq)]
+pub struct OverlaySnapshot {
+    overlay: BTreeMap<u64, Vec<u8>>,
+}
+
+/// A DwarfVm checkpoint, see `DwarfVm::checkpoint`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct VmCheckpoint {
+    snap: snapshot::Snapshot,
+}
+
+/// The first difference found between two DwarfVm states
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub enum StateDiff {
+    Pc {
+        expected: u64,
+        actual: u64,
+    },
+    StackLen {
+        expected: usize,
+        actual: usize,
+    },
+    /// idx counts from the bottom of the stack
+    Stack {
+        idx: usize,
+        expected: u64,
+        actual: u64,
+    },
+    /// The VM failed to step
+    Error(DwarfVmError),
+}
+
+/// One step of a golden trace, in the format of `res/sanity-states.json`
+///
+/// `stack` holds at most the top `TRACE_STACK_DEPTH` entries, bottom to top,
+/// while `stack_sz` is the full depth.
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TraceState {
+    pub pc: u64,
+    pub stack_sz: usize,
+    pub stack: Vec<u64>,
+}
+
+/// How many stack entries a `TraceState` keeps
+pub const TRACE_STACK_DEPTH: usize = 5;
+
+/// A recorded state that a live `DwarfVmState` can be checked against
+pub trait ExpectedState {
+    /// The first difference from `actual`, if any
+    fn diff(&self, actual: &DwarfVmState) -> Option<StateDiff>;
+}
+
+impl ExpectedState for DwarfVmState {
+    fn diff(&self, actual: &DwarfVmState) -> Option<StateDiff> {
+        DwarfVmState::diff(self, actual)
+    }
+}
+
+impl ExpectedState for TraceState {
+    /// Only the recorded top of the stack is compared, `idx` still counts from
+    /// the bottom of the full stack
+    fn diff(&self, actual: &DwarfVmState) -> Option<StateDiff> {
+        if self.pc != actual.pc {
+            return Some(StateDiff::Pc {
+                expected: self.pc,
+                actual: actual.pc,
+            });
+        }
+
+        if self.stack_sz != actual.stack.len() {
+            return Some(StateDiff::StackLen {
+                expected: self.stack_sz,
+                actual: actual.stack.len(),
+            });
+        }
+
+        let start = actual.stack.len().saturating_sub(self.stack.len());
+        self.stack
+            .iter()
+            .zip(&actual.stack[start..])
+            .enumerate()
+            .find(|(_, (e, a))| e != a)
+            .map(|(i, (e, a))| StateDiff::Stack {
+                idx: start + i,
+                expected: *e,
+                actual: *a,
+            })
+    }
+}
+
+/// Where and how a live run diverged from a recorded trace
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct TraceMismatch {
+    pub index: usize,
+    pub diff: StateDiff,
+}
+
+impl fmt::Display for TraceMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "trace mismatch at step {}: {:x?}", self.index, self.diff)
+    }
+}
+
+impl Error for TraceMismatch {}
+
+/// An error parsing the coredump ELF
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ElfError(pub &'static str);
+
+impl fmt::Display for ElfError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ELF parse error: {}", self.0)
+    }
+}
+
+impl Error for ElfError {}
+
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum DwarfVmError {
+    /// The bytes at `pc` could not be decoded, `bytes` are the first few
+    Decode {
+        pc: u64,
+        bytes: Vec<u8>,
+    },
+    /// A breakpoint at `pc` asked to stop execution
+    Breakpoint {
+        pc: u64,
+    },
+    /// The coredump could not be parsed
+    Elf(ElfError),
+    MissingSection(&'static str),
+    /// The section is compressed and could not be decompressed, see the
+    /// `compressed` feature
+    BadCompressedSection(&'static str),
+    BadLocList,
+    /// The data passed to `load` is not a valid saved VM
+    BadSnapshot,
+    /// The length prefix of an exprloc is malformed or longer than the data,
+    /// see `load_exprloc`
+    BadExprLoc,
+    /// No location list entry covers `target_pc`
+    NoLocation {
+        target_pc: u64,
+    },
+    /// The expression describes a value rather than its address, see
+    /// `eval_address`
+    NotAnAddress,
+    /// The expression describes an address rather than a value, see
+    /// `finalize`
+    NotAValue,
+    /// The op at `pc` would exceed the gas limit, see `set_gas_limit`
+    OutOfGas {
+        pc: u64,
+    },
+    /// The `piece`th piece of a composite location has no location
+    OptimizedOut {
+        piece: usize,
+    },
+    /// The pieces of a composite location add up to more than
+    /// `MAX_COMPOSITE_SIZE` bytes
+    CompositeTooLarge,
+    /// More memory reads were issued than `set_read_budget` allows
+    ReadBudgetExceeded {
+        pc: u64,
+    },
+    /// The expression did not finish with exactly one value on the stack
+    UnbalancedStack {
+        remaining: usize,
+    },
+    /// The core has no thread at index `idx`
+    NoSuchThread {
+        idx: usize,
+    },
+    /// The register save area at `ctx` is not mapped at `addr`, see
+    /// `validate_ctx`
+    BadContext {
+        ctx: u64,
+        addr: u64,
+    },
+    /// The register provider has no value for `reg`
+    NoRegister {
+        reg: u64,
+    },
+    /// `reg` is past the count given to `set_register_count`, or its slot
+    /// would be past the end of the address space
+    BadRegister {
+        reg: u64,
+    },
+    /// `addr` is not backed by the overlay or the core
+    Unmapped {
+        addr: u64,
+    },
+    /// The stack at `pc` didn't match an `assert_at`, stacks are bottom to top
+    AssertionFailed {
+        pc: u64,
+        expected: Vec<u64>,
+        actual: Vec<u64>,
+    },
+    /// The op at `pc` is not implemented, see `set_skip_unknown`
+    UnsupportedOp {
+        pc: u64,
+    },
+    /// The arithmetic op at `pc` overflowed, see `set_overflow_trap`
+    ArithmeticOverflow {
+        pc: u64,
+    },
+    /// A TLS op at `pc` was executed without a TLS base, see `set_tls_base`
+    NoTlsBase {
+        pc: u64,
+    },
+    /// The branch at `pc` left the program range for `target`, see
+    /// `set_escape_hook`
+    BranchOutOfRange {
+        pc: u64,
+        target: u64,
+    },
+    /// The `index`th branch differed from a recorded edge trace, see
+    /// `replay_edges`
+    TraceDivergence {
+        index: usize,
+    },
+    /// The `DW_OP_call` at `pc` would nest too deeply, see
+    /// `set_max_call_depth`
+    CallDepthExceeded {
+        pc: u64,
+    },
+    /// The op at `pc` has no fixed stack effect, see `static_stack_delta`
+    NoStaticDelta {
+        pc: u64,
+    },
+    /// A typed op at `pc` uses a type iris can't represent
+    UnsupportedTypedOp {
+        pc: u64,
+    },
 }
 
 impl fmt::Display for DwarfVmError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
+        match self {
+            DwarfVmError::Decode { pc, bytes } => {
+                write!(f, "could not decode op at {:#x} {:02x?}", pc, bytes)
+            }
+            DwarfVmError::Breakpoint { pc } => write!(f, "breakpoint at {:#x}", pc),
+            DwarfVmError::Elf(_) => write!(f, "could not parse core"),
+            DwarfVmError::MissingSection(name) => write!(f, "core has no {} section", name),
+            DwarfVmError::BadCompressedSection(name) => {
+                write!(f, "could not decompress {} section", name)
+            }
+            DwarfVmError::BadLocList => write!(f, "malformed location list"),
+            DwarfVmError::BadSnapshot => write!(f, "malformed saved VM"),
+            DwarfVmError::BadExprLoc => write!(f, "malformed exprloc"),
+            DwarfVmError::NoLocation { target_pc } => {
+                write!(f, "no location list entry covers {:#x}", target_pc)
+            }
+            DwarfVmError::NotAnAddress => write!(f, "expression has no address"),
+            DwarfVmError::NotAValue => write!(f, "expression has no value"),
+            DwarfVmError::OutOfGas { pc } => write!(f, "out of gas at {:#x}", pc),
+            DwarfVmError::OptimizedOut { piece } => write!(f, "piece {} is optimized out", piece),
+            DwarfVmError::CompositeTooLarge => write!(f, "composite location is too large"),
+            DwarfVmError::ReadBudgetExceeded { pc } => {
+                write!(f, "read budget exceeded at {:#x}", pc)
+            }
+            DwarfVmError::UnbalancedStack { remaining } => {
+                write!(f, "expected 1 value on the stack, found {}", remaining)
+            }
+            DwarfVmError::NoSuchThread { idx } => write!(f, "core has no thread {}", idx),
+            DwarfVmError::BadContext { ctx, addr } => {
+                write!(f, "context {:#x} is not mapped at {:#x}", ctx, addr)
+            }
+            DwarfVmError::NoRegister { reg } => write!(f, "no value for register {}", reg),
+            DwarfVmError::BadRegister { reg } => write!(f, "register {} is out of range", reg),
+            DwarfVmError::Unmapped { addr } => write!(f, "address {:#x} is not mapped", addr),
+            DwarfVmError::AssertionFailed {
+                pc,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "stack assertion failed at {:#x}, expected {:x?} found {:x?}",
+                pc, expected, actual
+            ),
+            DwarfVmError::UnsupportedOp { pc } => write!(f, "unsupported op at {:#x}", pc),
+            DwarfVmError::ArithmeticOverflow { pc } => {
+                write!(f, "arithmetic overflow at {:#x}", pc)
+            }
+            DwarfVmError::NoTlsBase { pc } => write!(f, "no TLS base set for op at {:#x}", pc),
+            DwarfVmError::BranchOutOfRange { pc, target } => {
+                write!(
+                    f,
+                    "branch at {:#x} leaves the program for {:#x}",
+                    pc, target
+                )
+            }
+            DwarfVmError::TraceDivergence { index } => {
+                write!(f, "branch {} differs from the recorded trace", index)
+            }
+            DwarfVmError::CallDepthExceeded { pc } => {
+                write!(f, "call depth exceeded at {:#x}", pc)
+            }
+            DwarfVmError::NoStaticDelta { pc } => {
+                write!(f, "op at {:#x} has no fixed stack effect", pc)
+            }
+            DwarfVmError::UnsupportedTypedOp { pc } => {
+                write!(f, "unsupported type for typed op at {:#x}", pc)
+            }
+        }
     }
 }
 
 impl Error for DwarfVmError {
     fn source(&self) -> Option<&(dyn Error + 'static)> {
-        None
+        match self {
+            DwarfVmError::Elf(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ElfError> for DwarfVmError {
+    fn from(e: ElfError) -> Self {
+        DwarfVmError::Elf(e)
+    }
+}
+
+/// How many bytes `set_decode_resync` will skip looking for a valid op
+const RESYNC_LIMIT: u64 = 16;
+
+/// The largest composite location, in bytes, see `DwarfVm::assemble_pieces`
+pub const MAX_COMPOSITE_SIZE: u64 = 0x10000;
+
+/// The poison fill used in deterministic mode, see `DwarfVm::set_deterministic`
+pub const DETERMINISTIC_FILL: u64 = 0xdead_beef_dead_beef;
+
+/// Get the named operands of `op`, e.g. `[("reg", 6), ("offset", -8)]`
+///
+/// Ops without operands return an empty list.
+pub fn op_operands(op: &Op) -> Vec<(&'static str, i128)> {
+    match *op {
+        Op::Addr(a) => vec![("address", a as i128)],
+        Op::Const1u(v) => vec![("value", v as i128)],
+        Op::Const1s(v) => vec![("value", v as i128)],
+        Op::Const2u(v) => vec![("value", v as i128)],
+        Op::Const2s(v) => vec![("value", v as i128)],
+        Op::Const4u(v) => vec![("value", v as i128)],
+        Op::Const4s(v) => vec![("value", v as i128)],
+        Op::Const8u(v) | Op::Constu(v) => vec![("value", v as i128)],
+        Op::Const8s(v) | Op::Consts(v) => vec![("value", v as i128)],
+        Op::Pick(idx) => vec![("index", idx as i128)],
+        Op::PlusConst(v) => vec![("value", v as i128)],
+        Op::Bra(off) | Op::Skip(off) => vec![("offset", off as i128)],
+        Op::Lit(v) => vec![("value", v as i128)],
+        Op::Reg(r) => vec![("reg", r as i128)],
+        Op::BReg(r, off) => vec![("reg", r as i128), ("offset", off as i128)],
+        Op::RegX(r) => vec![("reg", r as i128)],
+        Op::BRegX(r, off) => vec![("reg", r as i128), ("offset", off as i128)],
+        Op::DerefSize(sz) => vec![("size", sz as i128)],
+        Op::Deref
+        | Op::Dup
+        | Op::Drop
+        | Op::Over
+        | Op::Swap
+        | Op::Rot
+        | Op::Abs
+        | Op::And
+        | Op::Div
+        | Op::Minus
+        | Op::Mod
+        | Op::Mul
+        | Op::Neg
+        | Op::Not
+        | Op::Or
+        | Op::Plus
+        | Op::Shl
+        | Op::Shr
+        | Op::Shra
+        | Op::Xor
+        | Op::Eq
+        | Op::Ge
+        | Op::Gt
+        | Op::Le
+        | Op::Lt
+        | Op::Ne
+        | Op::Nop => vec![],
+    }
+}
+
+/// Decode every op in `bytes`, yielding each op's size along with the op
+///
+/// No VM is needed, this is handy for inspecting expressions outside of a
+/// core. Decoding stops after the first error, whose `pc` is the offset of the
+/// bad op in `bytes`. Extended ops, see `ExtOp`, can't be represented as an
+/// `Op`, so they come back as `DwarfVmError::Decode` errors like any other
+/// byte `dwarf_dis` doesn't know.
+pub fn decode_all(bytes: &[u8]) -> impl Iterator<Item = Result<(usize, Op), DwarfVmError>> + '_ {
+    let mut off = 0;
+    let mut failed = false;
+
+    std::iter::from_fn(move || {
+        if failed || off >= bytes.len() {
+            return None;
+        }
+
+        let data = &bytes[off..];
+
+        let r = decode(data).map_err(|_| decode_error(off as u64, data));
+
+        match &r {
+            Ok((sz, _)) => off += sz,
+            Err(_) => failed = true,
+        }
+
+        Some(r)
+    })
+}
+
+/// How many bytes a `DwarfVmError::Decode` captures
+const DECODE_ERROR_BYTES: usize = 8;
+
+fn decode_error(pc: u64, data: &[u8]) -> DwarfVmError {
+    DwarfVmError::Decode {
+        pc,
+        bytes: data.iter().take(DECODE_ERROR_BYTES).copied().collect(),
+    }
+}
+
+/// The net change in stack depth from executing `op`
+///
+/// Returns `None` for branches, whose effect depends on the path taken.
+fn op_delta(op: &Op) -> Option<i64> {
+    let delta = match op {
+        // pop an address, push a value
+        Op::Deref | Op::DerefSize(_) => 0,
+        // push a constant, address or register
+        Op::Addr(_)
+        | Op::Const1u(_)
+        | Op::Const1s(_)
+        | Op::Const2u(_)
+        | Op::Const2s(_)
+        | Op::Const4u(_)
+        | Op::Const4s(_)
+        | Op::Const8u(_)
+        | Op::Constu(_)
+        | Op::Const8s(_)
+        | Op::Consts(_)
+        | Op::Lit(_)
+        | Op::Reg(_)
+        | Op::RegX(_)
+        | Op::BReg(_, _)
+        | Op::BRegX(_, _) => 1,
+        // copy an existing entry
+        Op::Dup | Op::Over | Op::Pick(_) => 1,
+        Op::Drop => -1,
+        // reorder in place
+        Op::Swap | Op::Rot => 0,
+        // unary, pop one push one
+        Op::Abs | Op::Neg | Op::Not | Op::PlusConst(_) => 0,
+        // binary, pop two push one
+        Op::And
+        | Op::Div
+        | Op::Minus
+        | Op::Mod
+        | Op::Mul
+        | Op::Or
+        | Op::Plus
+        | Op::Shl
+        | Op::Shr
+        | Op::Shra
+        | Op::Xor
+        | Op::Eq
+        | Op::Ge
+        | Op::Gt
+        | Op::Le
+        | Op::Lt
+        | Op::Ne => -1,
+        Op::Nop => 0,
+        Op::Bra(_) | Op::Skip(_) => return None,
+    };
+
+    Some(delta)
+}
+
+/// The net change in stack depth from executing `op`, see `op_delta`
+fn ext_op_delta(op: &ExtOp) -> Option<i64> {
+    let delta = match op {
+        // pop an offset, push an address
+        ExtOp::FormTlsAddress => 0,
+        ExtOp::EntryValue(_) | ExtOp::Addrx(_) | ExtOp::Constx(_) => 1,
+        ExtOp::ConstType { .. } => 1,
+        ExtOp::Convert(_) => 0,
+        // sets the result without touching the stack
+        ExtOp::ImplicitValue(_) | ExtOp::StackValue => 0,
+        // pops an address only if the piece is in memory
+        ExtOp::Piece(_) | ExtOp::BitPiece { .. } => return None,
+        // depends on the expression called
+        ExtOp::Call(_) => return None,
+    };
+
+    Some(delta)
+}
+
+/// Find `a` in a set of memory regions, if regions overlap the lowest base wins
+fn region_lookup<V: AsRef<[u8]>>(regions: &BTreeMap<u64, V>, a: u64) -> Option<&[u8]> {
+    regions.range(..=a).find_map(|(start, v)| {
+        let off = (a - *start) as usize;
+
+        v.as_ref().get(off..).filter(|x| !x.is_empty())
+    })
+}
+
+/// Total the bits of `pieces`, see `add_piece_bits`
+fn composite_bits(pieces: &[Piece]) -> Result<u64, DwarfVmError> {
+    pieces
+        .iter()
+        .try_fold(0, |total, x| add_piece_bits(total, x.size, x.offset))
+}
+
+/// Add a piece to a composite of `total` bits, keeping it within
+/// `MAX_COMPOSITE_SIZE` and the piece's bits addressable
+fn add_piece_bits(total: u64, size: u64, offset: u64) -> Result<u64, DwarfVmError> {
+    offset
+        .checked_add(size)
+        .and_then(|_| total.checked_add(size))
+        .filter(|&x| x <= MAX_COMPOSITE_SIZE * 8)
+        .ok_or(DwarfVmError::CompositeTooLarge)
+}
+
+/// The bytes of a core, borrowed from the caller or owned by the VM
+enum CoreData<'a> {
+    Borrowed(&'a [u8]),
+    Owned(Arc<[u8]>),
+}
+
+impl CoreData<'_> {
+    fn bytes(&self) -> &[u8] {
+        match self {
+            CoreData::Borrowed(x) => x,
+            CoreData::Owned(x) => x,
+        }
     }
 }
 
@@ -38,9 +961,58 @@ pub struct DwarfVm<'a> {
     pub pc: u64,
     pub stack: Vec<u64>,
     ctx: u64,
+    entry: u64,
+    insn_count: u64,
+    max_stack_depth: usize,
+    resume_pc: Option<u64>,
+    bkpt_error: Option<DwarfVmError>,
+    edges: Option<Vec<(u64, u64, bool)>>,
+    reads: u64,
+    read_budget: Option<u64>,
+    program_start: Option<u64>,
+    program_end: Option<u64>,
+    gas_costs: GasTable,
+    gas_limit: Option<u64>,
+    gas_used: u64,
+    addr_size: u8,
+    generic_size: Option<u8>,
+    addr_bias: i64,
+    load_bias: i64,
+    ptr_auth_mask: u64,
+    skip_unknown: bool,
+    overflow_trap: bool,
+    signed_overflow: bool,
+    trace_depth: Option<usize>,
+    decode_resync: bool,
+    resync_skipped: u64,
+    unmapped_fill: Option<u64>,
+    deterministic: bool,
+    short_read: ShortReadPolicy,
+    bool_repr: BoolRepr,
     overlay: BTreeMap<u64, Vec<u8>>,
+    mapped: BTreeMap<u64, &'a [u8]>,
+    #[cfg(feature = "linux-live")]
+    live: Option<ProcMem>,
+    events: Option<Vec<VmEvent>>,
+    implicit: Option<Vec<u8>>,
+    stack_value: bool,
+    pieces: Vec<Piece>,
+    reg_location: Option<u64>,
+    tls_base: Option<u64>,
+    call_targets: BTreeMap<u64, (u64, u64)>,
+    base_types: BTreeMap<u64, (u8, bool)>,
+    call_stack: Vec<(u64, u64)>,
+    max_call_depth: Option<usize>,
+    reg_count: Option<u64>,
+    reg_provider: Option<Box<dyn FnMut(u64) -> Result<u64, DwarfVmError>>>,
     breakpoints: BTreeMap<u64, Box<dyn FnMut(&mut Self, &mut Op) -> bool>>,
-    core: ElfFile<'a>,
+    stack_observer: Option<Box<dyn FnMut(StackEvent)>>,
+    boundary_read_hook: Option<Box<dyn FnMut(u64, usize, usize)>>,
+    nop_hook: Option<Box<dyn FnMut(&mut Self) -> ControlFlow<()>>>,
+    escape_hook: Option<Box<dyn FnMut(&mut Self, u64) -> ControlFlow<()>>>,
+    pre_step: Option<Box<dyn FnMut(&Self, &Op)>>,
+    post_step: Option<Box<dyn FnMut(&Self, &Op)>>,
+    core: Option<CoreData<'a>>,
 }
 
 impl<'a> fmt::Display for DwarfVm<'a> {
@@ -53,6 +1025,21 @@ impl<'a> fmt::Display for DwarfVm<'a> {
     }
 }
 
+impl DwarfVm<'static> {
+    /// Create a new Dwarf VM which owns its core
+    ///
+    /// The VM has no borrowed lifetime, so it can be stored in long lived
+    /// structures. The core is freed with the last VM sharing it. Returns an
+    /// error if the core can't be parsed, see `new` for a description of the
+    /// arguments.
+    pub fn new_owned<C: Into<Arc<[u8]>>>(pc: u64, ctx: u64, core: C) -> Result<Self, DwarfVmError> {
+        let core = core.into();
+        ElfFile::new(&core).map_err(ElfError)?;
+
+        Ok(Self::with_core(pc, ctx, Some(CoreData::Owned(core))))
+    }
+}
+
 impl<'a> DwarfVm<'a> {
     /// Create a new Dwarf VM
     ///
@@ -62,283 +1049,2263 @@ impl<'a> DwarfVm<'a> {
     /// core: the backing memory for the emulator, can be created via `gcore`
     /// from gdb.
     pub fn new(pc: u64, ctx: u64, core: &'a [u8]) -> Self {
+        Self::try_new(pc, ctx, core).expect("Could not parse core")
+    }
+
+    /// Create a new Dwarf VM, returning an error if the core can't be parsed
+    ///
+    /// See `new` for a description of the arguments.
+    pub fn try_new(pc: u64, ctx: u64, core: &'a [u8]) -> Result<Self, DwarfVmError> {
+        ElfFile::new(core).map_err(ElfError)?;
+
+        Ok(Self::with_core(pc, ctx, Some(CoreData::Borrowed(core))))
+    }
+
+    /// Create a new Dwarf VM with no core
+    ///
+    /// All memory, including the program, must be supplied through the
+    /// overlay or `map_borrowed`, anything else reads as unmapped. Core only
+    /// features such as `threads` find nothing. See `new` for a description of
+    /// the arguments.
+    pub fn without_memory(pc: u64, ctx: u64) -> Self {
+        Self::with_core(pc, ctx, None)
+    }
+
+    fn with_core(pc: u64, ctx: u64, core: Option<CoreData<'a>>) -> Self {
         let stack = Default::default();
-        let core = ElfFile::new(&core).expect("Could not parse core");
 
         Self {
             pc,
             ctx,
             stack,
+            entry: pc,
+            insn_count: 0,
+            max_stack_depth: 0,
+            resume_pc: None,
+            bkpt_error: None,
+            edges: None,
+            reads: 0,
+            read_budget: None,
+            program_start: None,
+            program_end: None,
+            gas_costs: GasTable::default(),
+            gas_limit: None,
+            gas_used: 0,
+            addr_size: 8,
+            generic_size: None,
+            addr_bias: 0,
+            load_bias: 0,
+            ptr_auth_mask: 0,
+            skip_unknown: false,
+            overflow_trap: false,
+            signed_overflow: false,
+            trace_depth: None,
+            decode_resync: false,
+            resync_skipped: 0,
+            unmapped_fill: None,
+            deterministic: false,
+            short_read: ShortReadPolicy::default(),
+            bool_repr: BoolRepr::default(),
             overlay: BTreeMap::default(),
+            mapped: BTreeMap::default(),
+            #[cfg(feature = "linux-live")]
+            live: None,
+            events: None,
+            implicit: None,
+            stack_value: false,
+            pieces: Vec::new(),
+            reg_location: None,
+            tls_base: None,
+            call_targets: BTreeMap::new(),
+            base_types: BTreeMap::new(),
+            call_stack: Vec::new(),
+            max_call_depth: None,
+            reg_count: None,
+            reg_provider: None,
             breakpoints: BTreeMap::default(),
+            stack_observer: None,
+            boundary_read_hook: None,
+            nop_hook: None,
+            escape_hook: None,
+            pre_step: None,
+            post_step: None,
             core,
         }
     }
 
-    /// Execute a single Dwarf VM instruction
-    pub fn step(&mut self) -> Result<(), DwarfVmError> {
-        let (sz, mut op) = decode(self.target_read(self.pc)).map_err(|_| DwarfVmError::Decode)?;
+    /// Create a new Dwarf VM, checking `pc` and `ctx` are mapped in the core
+    ///
+    /// Returns `DwarfVmError::Unmapped` with the offending address instead of
+    /// failing on the first step. See `new` for a description of the arguments.
+    pub fn new_checked(pc: u64, ctx: u64, core: &'a [u8]) -> Result<Self, DwarfVmError> {
+        let dvm = Self::try_new(pc, ctx, core)?;
+
+        for &addr in &[pc, ctx] {
+            if dvm.try_target_read(addr).map_or(true, |x| x.is_empty()) {
+                return Err(DwarfVmError::Unmapped { addr });
+            }
+        }
 
-        let bkpt = self.breakpoints.remove(&self.pc);
+        Ok(dvm)
+    }
 
-        if let Some(mut bkpt) = bkpt {
-            let bail = bkpt(self, &mut op);
+    /// Create a new Dwarf VM executing `program` rather than bytes in the core
+    ///
+    /// `program` is mapped into the overlay at `pc_base`, which is also the
+    /// initial PC. Data reads which miss the program still go to `core`. See
+    /// `new` for a description of the other arguments.
+    pub fn new_with_program(pc_base: u64, program: Vec<u8>, ctx: u64, core: &'a [u8]) -> Self {
+        let mut dvm = Self::new(pc_base, ctx, core);
+        dvm.overlay.insert(pc_base, program);
+        dvm
+    }
 
-            self.breakpoints.insert(self.pc, bkpt);
+    /// Reserve room for `n` stack entries up front
+    ///
+    /// The reservation survives `reset`, `set_state` and `set_stack`, so
+    /// expressions which push many values don't reallocate the stack as they
+    /// run.
+    pub fn with_stack_capacity(mut self, n: usize) -> Self {
+        self.stack.reserve(n);
+        self
+    }
 
-            if bail {
-                return Err(DwarfVmError::Breakpoint);
-            }
+    /// Create a new Dwarf VM using the registers of a thread from the core
+    ///
+    /// Instead of reading registers through a context structure, the register
+    /// provider is seeded from the `NT_PRSTATUS` note of the `idx`th thread.
+    /// See `threads`.
+    pub fn from_thread(pc: u64, core: &'a [u8], idx: usize) -> Result<Self, DwarfVmError> {
+        let mut dvm = Self::try_new(pc, 0, core)?;
+        dvm.select_thread(idx)?;
+
+        Ok(dvm)
+    }
+
+    /// Read registers from the `idx`th thread of the core
+    ///
+    /// This replaces the register provider and, if the thread has one, the TLS
+    /// base. The stack and PC are left alone, so the same expression can be
+    /// evaluated against each thread in turn. See `threads`.
+    pub fn select_thread(&mut self, idx: usize) -> Result<(), DwarfVmError> {
+        let thread = self
+            .threads()
+            .into_iter()
+            .nth(idx)
+            .ok_or(DwarfVmError::NoSuchThread { idx })?;
+
+        if let Some(tp) = thread.thread_pointer() {
+            self.set_tls_base(tp);
+        }
+
+        self.set_register_provider(move |reg| {
+            thread
+                .dwarf_reg(reg)
+                .ok_or(DwarfVmError::NoRegister { reg })
+        });
+
+        Ok(())
+    }
+
+    /// Get the registers of every thread in the core
+    ///
+    /// These are parsed from the `NT_PRSTATUS` notes, only 64-bit cores are
+    /// supported.
+    pub fn threads(&self) -> Vec<ThreadRegs> {
+        self.elf()
+            .map_or_else(Vec::new, |core| notes::threads(&core))
+    }
+
+    /// Iterate `(type, name, desc)` for every note in the core
+    ///
+    /// This covers all notes, not just registers, e.g. `NT_FILE` mappings and
+    /// `NT_AUXV`. Names have their NUL terminator stripped.
+    pub fn notes(&self) -> impl Iterator<Item = (u32, &str, &[u8])> {
+        let segments: Vec<&[u8]> = match self.elf() {
+            Some(core) => notes::note_segments(&core).collect(),
+            None => Vec::new(),
         };
 
-        self.pc += sz as u64;
+        segments
+            .into_iter()
+            .flat_map(notes::notes)
+            .map(|(ty, name, desc)| {
+                let name = name.split(|&x| x == 0).next().unwrap_or(&[]);
 
-        match op {
-            Op::Addr(a) => self.push(self.target_read_u64(a)),
-            Op::Deref => {
-                let t = self.pop();
-                self.push(self.target_read_u64(t))
-            }
-            Op::Const1u(v) => self.push(v as u64),
-            Op::Const1s(v) => self.push(v as u64),
-            Op::Const2u(v) => self.push(v as u64),
-            Op::Const2s(v) => self.push(v as u64),
-            Op::Const4u(v) => self.push(v as u64),
-            Op::Const4s(v) => self.push(v as u64),
-            Op::Const8u(v) | Op::Constu(v) => self.push(v as u64),
-            Op::Const8s(v) | Op::Consts(v) => self.push(v as u64),
+                (ty, std::str::from_utf8(name).unwrap_or(""), desc)
+            })
+    }
+
+    /// Set the address of the dwarf context structure
+    ///
+    /// The context is an array of pointers to the saved registers, indexed by
+    /// register number. Like any other memory it is read through the overlay
+    /// first, so a synthetic context can be built there, even with
+    /// `without_memory`.
+    pub fn set_ctx(&mut self, ctx: u64) {
+        self.ctx = ctx;
+    }
+
+    /// Get the address of the dwarf context structure
+    pub fn ctx(&self) -> u64 {
+        self.ctx
+    }
+
+    /// Check the context structure holds `expected_regs` mapped registers
+    ///
+    /// This catches a context picked from the wrong stack trace argument, as
+    /// the whole of `ctx..ctx + expected_regs * 8` must be mapped. An array
+    /// which runs off the end of the address space is never valid.
+    pub fn validate_ctx(&self, expected_regs: usize) -> Result<(), DwarfVmError> {
+        let len = (expected_regs as u64)
+            .checked_mul(8)
+            .filter(|&len| self.ctx.checked_add(len).is_some());
+
+        let unmapped = match len {
+            Some(len) => self.unmapped_in(self.ctx, len),
+            None => Some(
+                self.unmapped_in(self.ctx, u64::MAX - self.ctx)
+                    .unwrap_or(u64::MAX),
+            ),
+        };
+
+        match unmapped {
+            Some(addr) => Err(DwarfVmError::BadContext {
+                ctx: self.ctx,
+                addr,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Set the target address size in bytes
+    ///
+    /// This controls how many bytes a plain `DW_OP_deref` reads and, unless
+    /// `set_generic_type_size` is used, the width of the DWARF generic type:
+    /// with a 4 byte address size every value pushed is truncated to 32 bits,
+    /// so arithmetic wraps as it would on the target. Only 4 and 8 are valid,
+    /// the default is 8.
+    pub fn set_addr_size(&mut self, sz: u8) {
+        assert!(sz == 4 || sz == 8, "Bad address size ({})", sz);
+
+        self.addr_size = sz;
+    }
+
+    /// Get the target address size in bytes
+    pub fn addr_size(&self) -> u8 {
+        self.addr_size
+    }
+
+    /// Set the width of the DWARF generic type in bytes
+    ///
+    /// Every value pushed is truncated to this width and signed ops sign extend
+    /// from it. Only 1, 2, 4 and 8 are valid, by default it follows
+    /// `set_addr_size`.
+    pub fn set_generic_type_size(&mut self, sz: u8) {
+        assert!(
+            matches!(sz, 1 | 2 | 4 | 8),
+            "Bad generic type size ({})",
+            sz
+        );
+
+        self.generic_size = Some(sz);
+    }
+
+    /// Get the width of the DWARF generic type in bytes
+    pub fn generic_type_size(&self) -> u8 {
+        self.generic_size.unwrap_or(self.addr_size)
+    }
+
+    /// Cap the number of memory reads the VM may issue
+    ///
+    /// Once more than `n` reads have been issued since creation or the last
+    /// `reset`, the offending step returns `DwarfVmError::ReadBudgetExceeded`.
+    /// Instruction fetches are not counted.
+    pub fn set_read_budget(&mut self, n: u64) {
+        self.read_budget = Some(n);
+    }
+
+    /// The number of memory reads issued since creation or the last `reset`
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    /// Set the gas charged for each kind of op, see `set_gas_limit`
+    pub fn set_gas_costs(&mut self, costs: GasTable) {
+        self.gas_costs = costs;
+    }
+
+    /// Cap the gas the VM may use
+    ///
+    /// Each op is charged according to the `GasTable`, see `set_gas_costs`.
+    /// An op which would take the gas used since creation or the last `reset`
+    /// past `n` returns `DwarfVmError::OutOfGas` without executing.
+    pub fn set_gas_limit(&mut self, n: u64) {
+        self.gas_limit = Some(n);
+    }
+
+    /// The gas used since creation or the last `reset`
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    /// The number of instructions executed since creation or the last `reset`
+    pub fn insn_count(&self) -> u64 {
+        self.insn_count
+    }
+
+    /// The deepest the stack has been since creation or the last `reset`
+    pub fn max_stack_depth(&self) -> usize {
+        self.max_stack_depth
+    }
+
+    /// Start recording the branches taken, see `edge_trace`
+    pub fn enable_edge_trace(&mut self) {
+        self.edges.get_or_insert_with(Vec::new);
+    }
+
+    /// Get the recorded branches as `(from_pc, to_pc, taken)` tuples
+    ///
+    /// Every executed `DW_OP_bra` and `DW_OP_skip` is recorded, `to_pc` is
+    /// where execution continued so for an untaken `DW_OP_bra` it is the next
+    /// op. Cleared on `reset`, empty unless `enable_edge_trace` was called.
+    pub fn edge_trace(&self) -> &[(u64, u64, bool)] {
+        self.edges.as_deref().unwrap_or(&[])
+    }
+
+    /// Reset the VM to its entry PC with an empty stack
+    ///
+    /// The overlay and breakpoints are kept.
+    pub fn reset(&mut self) {
+        self.pc = self.entry;
+        self.stack.clear();
+        self.insn_count = 0;
+        self.max_stack_depth = 0;
+        self.resume_pc = None;
+        if let Some(edges) = &mut self.edges {
+            edges.clear();
+        }
+        self.reads = 0;
+        self.gas_used = 0;
+        self.resync_skipped = 0;
+        self.implicit = None;
+        self.stack_value = false;
+        self.pieces.clear();
+        self.reg_location = None;
+        self.call_stack.clear();
+    }
+
+    /// Set the base address TLS offsets are relative to
+    ///
+    /// `DW_OP_form_tls_address` pops an offset and pushes `base + offset`. No
+    /// module lookup is done, so `base` should be the start of the TLS block
+    /// of the module the expression belongs to. `from_thread` seeds this with
+    /// the raw thread pointer, which may need adjusting for the target's TLS
+    /// layout.
+    pub fn set_tls_base(&mut self, base: u64) {
+        self.tls_base = Some(base);
+    }
+
+    /// Resolve `DW_OP_call2` and `DW_OP_call4` of DIE offset `die` to the
+    /// expression at `start..end`
+    ///
+    /// The expression must already be mapped, e.g. with `map_program_aligned`.
+    /// Reaching `end` returns to the op after the call. Calls to a DIE with no
+    /// target are unsupported, see `set_skip_unknown`.
+    pub fn add_call_target(&mut self, die: u64, start: u64, end: u64) {
+        self.call_targets.insert(die, (start, end));
+    }
+
+    /// Describe the base type at DIE offset `die` for `DW_OP_convert`
+    ///
+    /// Only integer types of 1, 2, 4 or 8 bytes are supported, converting to
+    /// one truncates the value to `size` bytes, then sign extends it if
+    /// `signed`. Converting to an unknown type fails with
+    /// `DwarfVmError::UnsupportedTypedOp`.
+    pub fn add_base_type(&mut self, die: u64, size: u8, signed: bool) {
+        self.base_types.insert(die, (size, signed));
+    }
+
+    /// Cap the number of nested `DW_OP_call`s
+    ///
+    /// A call which would nest deeper than `n` fails with
+    /// `DwarfVmError::CallDepthExceeded`. The call stack lives on the heap, so
+    /// without a cap recursive expressions run until another limit is hit.
+    pub fn set_max_call_depth(&mut self, n: usize) {
+        self.max_call_depth = Some(n);
+    }
+
+    /// The number of `DW_OP_call`s currently active
+    pub fn call_depth(&self) -> usize {
+        self.call_stack.len()
+    }
+
+    /// Skip ops the VM doesn't implement rather than erroring
+    ///
+    /// Skipped ops are logged via warn and otherwise treated as a nop. This
+    /// gives an approximate trace past unknown ops, so is off by default.
+    pub fn set_skip_unknown(&mut self, skip: bool) {
+        self.skip_unknown = skip;
+    }
+
+    /// Fail on overflow in address arithmetic rather than wrapping
+    ///
+    /// `DW_OP_plus`, `DW_OP_plus_uconst`, `DW_OP_minus` and `DW_OP_mul` fail
+    /// with `DwarfVmError::ArithmeticOverflow` if the result doesn't fit the
+    /// generic type, e.g. an address wrapping around the address space. The
+    /// operands are unsigned unless `set_signed_overflow` is used. Off by
+    /// default, as wrapping is what DWARF specifies.
+    pub fn set_overflow_trap(&mut self, trap: bool) {
+        self.overflow_trap = trap;
+    }
+
+    /// Check for signed rather than unsigned overflow, see `set_overflow_trap`
+    ///
+    /// Operands are sign extended from the generic type and the result must
+    /// fit its signed range, so e.g. adding to a negative offset doesn't trap.
+    /// The constant of `DW_OP_plus_uconst` is still unsigned.
+    pub fn set_signed_overflow(&mut self, signed: bool) {
+        self.signed_overflow = signed;
+    }
+
+    /// Set how many stack entries `run` traces before each step
+    ///
+    /// `None`, the default, disables the per-step trace. See `trace_state`.
+    pub fn set_trace_depth(&mut self, depth: Option<usize>) {
+        self.trace_depth = depth;
+    }
+
+    /// Skip undecodable bytes rather than erroring
+    ///
+    /// When the op at the PC fails to decode, the PC is advanced a byte at a
+    /// time until an op decodes, giving up after a few bytes. This is a best
+    /// effort for damaged expressions, see `resync_skipped` for how many bytes
+    /// were skipped.
+    pub fn set_decode_resync(&mut self, resync: bool) {
+        self.decode_resync = resync;
+    }
+
+    /// The number of bytes skipped by `set_decode_resync` since creation or the
+    /// last `reset`
+    pub fn resync_skipped(&self) -> u64 {
+        self.resync_skipped
+    }
+
+    /// Satisfy reads of unmapped memory with a fixed poison value
+    ///
+    /// By default reads of unmapped memory fail with `DwarfVmError::Unmapped`.
+    /// With a fill set they instead return the low bytes of `fill`, so an
+    /// expression can run to completion over an incomplete core. Instruction
+    /// fetches are never filled.
+    pub fn set_unmapped_fill(&mut self, fill: Option<u64>) {
+        self.unmapped_fill = fill;
+    }
+
+    /// Only depend on the core, PC, context and overlay
+    ///
+    /// Running the same core, PC and context twice in deterministic mode gives
+    /// identical traces, regardless of the machine. In this mode:
+    ///
+    /// * any unmapped fill is replaced with `DETERMINISTIC_FILL`
+    /// * live memory, see `set_live_memory`, is ignored
+    ///
+    /// The VM has no wall clock dependent behavior, so nothing else changes.
+    pub fn set_deterministic(&mut self, deterministic: bool) {
+        self.deterministic = deterministic;
+    }
+
+    /// Is deterministic mode enabled, see `set_deterministic`
+    pub fn deterministic(&self) -> bool {
+        self.deterministic
+    }
+
+    /// The unmapped fill in effect, see `set_deterministic`
+    fn poison(&self) -> Option<u64> {
+        match self.unmapped_fill {
+            Some(_) if self.deterministic => Some(DETERMINISTIC_FILL),
+            fill => fill,
+        }
+    }
+
+    /// Set what typed reads do when they run off the end of their region
+    ///
+    /// This applies when the first byte of a read is mapped but not all of
+    /// them are, see `ShortReadPolicy`. The default is
+    /// `ShortReadPolicy::Error`.
+    pub fn set_short_read_policy(&mut self, policy: ShortReadPolicy) {
+        self.short_read = policy;
+    }
+
+    /// Set what comparison ops push for true
+    ///
+    /// The default, `BoolRepr::OneZero`, is what the DWARF spec requires.
+    /// `BoolRepr::AllOnes` is only for matching implementations which push a
+    /// mask, e.g. when differential testing against them.
+    pub fn set_bool_repr(&mut self, repr: BoolRepr) {
+        self.bool_repr = repr;
+    }
+
+    /// Set the load bias, the runtime address minus the core's address
+    ///
+    /// Use this when the core's sections and segments are at link time
+    /// addresses but the target ran relocated, e.g. a PIE under ASLR. The bias
+    /// applies to exactly two things:
+    ///
+    /// * core sections and segments are treated as mapped at their address
+    ///   plus the bias
+    /// * `DW_OP_addr` operands, which are link time addresses, have the bias
+    ///   added, on top of any `set_addr_bias`
+    ///
+    /// Everything else, the PC, the context, overlay addresses, register
+    /// values and values read from memory, is a runtime address and used as
+    /// is.
+    pub fn set_load_bias(&mut self, bias: i64) {
+        self.load_bias = bias;
+    }
+
+    /// Set the pointer authentication bits to strip before dereferencing
+    ///
+    /// Values popped by `DW_OP_deref` and `DW_OP_deref_size`, and the saved
+    /// register pointers in the context structure, have these bits cleared
+    /// before being used as an address. The pushed values themselves are left
+    /// alone. Defaults to 0, no stripping.
+    pub fn set_ptr_auth_mask(&mut self, mask: u64) {
+        self.ptr_auth_mask = mask;
+    }
+
+    /// Set a bias added to every `DW_OP_addr` operand
+    ///
+    /// This re-bases expressions captured assuming a different load address.
+    /// Only `DW_OP_addr` operands are affected, register and branch ops are
+    /// left alone as they are already position independent.
+    pub fn set_addr_bias(&mut self, bias: i64) {
+        self.addr_bias = bias;
+    }
+
+    /// Load the location expression covering `target_pc` as the program to run
+    ///
+    /// `section_offset` is the offset of the list in `.debug_loclists`, or in
+    /// `.debug_loc` if the core has no DWARF 5 location lists. `cu_base` is
+    /// the base address of the list's compilation unit, its `DW_AT_low_pc`,
+    /// which offset entries are relative to. Compressed sections are
+    /// supported with the `compressed` feature. The expression
+    /// is copied into the overlay at `EXPR_BASE`, the PC is pointed at it and
+    /// the stack is cleared.
+    pub fn load_location_list(
+        &mut self,
+        section_offset: u64,
+        target_pc: u64,
+        cu_base: u64,
+    ) -> Result<(), DwarfVmError> {
+        let off = section_offset as usize;
+
+        let lookup = |name: &str| self.named_section(name);
+
+        let expr = if let Some(data) = compress::section_data(lookup, ".debug_loclists")? {
+            loclist::find_loclists(&data, off, target_pc, cu_base, self.addr_size)?.to_vec()
+        } else if let Some(data) = compress::section_data(lookup, ".debug_loc")? {
+            loclist::find_loc(&data, off, target_pc, cu_base, self.addr_size)?.to_vec()
+        } else {
+            return Err(DwarfVmError::MissingSection(".debug_loc"));
+        };
+
+        self.overlay.insert(EXPR_BASE, expr);
+        self.entry = EXPR_BASE;
+        self.pc = EXPR_BASE;
+        self.program_start = None;
+        self.program_end = None;
+        self.stack.clear();
+
+        Ok(())
+    }
+
+    /// Load a single location expression, e.g. a DIE's `DW_AT_location`
+    ///
+    /// Like `load_location_list`, the expression is copied into the overlay,
+    /// here at `base`, the PC is pointed at it and the stack is cleared.
+    pub fn load_location_expr(&mut self, bytes: &[u8], base: u64) {
+        self.overlay.insert(base, bytes.to_vec());
+        self.entry = base;
+        self.pc = base;
+        self.program_start = None;
+        self.program_end = None;
+        self.stack.clear();
+    }
+
+    /// Load a length prefixed expression, e.g. a `DW_FORM_exprloc` attribute
+    ///
+    /// `bytes` starts with the ULEB128 length of the expression, exactly that
+    /// many of the following bytes are loaded at `base` as by
+    /// `load_location_expr`, and the program range is set to cover exactly
+    /// them, see `set_program_start` and `set_program_end`. The expression
+    /// must end within the address space.
+    pub fn load_exprloc(&mut self, bytes: &[u8], base: u64) -> Result<(), DwarfVmError> {
+        let (len, off) = ext::read_uleb(bytes).ok_or(DwarfVmError::BadExprLoc)?;
+
+        let expr = off
+            .checked_add(len as usize)
+            .and_then(|end| bytes.get(off..end))
+            .ok_or(DwarfVmError::BadExprLoc)?;
+        let end = base.checked_add(len).ok_or(DwarfVmError::BadExprLoc)?;
+
+        self.load_location_expr(expr, base);
+        self.program_start = Some(base);
+        self.program_end = Some(end);
+
+        Ok(())
+    }
+
+    /// Stop fetching ops at `end`
+    ///
+    /// An op at `end` is treated as unmapped, as are the bytes of an op which
+    /// straddles it, so the VM can't run off the end of an expression into
+    /// whatever follows it. Branches past `end` are escapes, see
+    /// `set_escape_hook`. Data reads are unaffected.
+    pub fn set_program_end(&mut self, end: Option<u64>) {
+        self.program_end = end;
+    }
+
+    /// Treat branches before `start` as escapes, see `set_escape_hook`
+    pub fn set_program_start(&mut self, start: Option<u64>) {
+        self.program_start = start;
+    }
+
+    /// Be told when a branch leaves the program range
+    ///
+    /// The range is set by `set_program_start` and `set_program_end`, falling
+    /// off the end is not an escape. `f` is given the branch target, which the
+    /// PC is already at, and can `Break` to stop after the branch, as with a
+    /// breakpoint, or `Continue` to run on outside the range. Without a hook
+    /// escapes fail with `DwarfVmError::BranchOutOfRange`.
+    pub fn set_escape_hook<F: 'static + FnMut(&mut Self, u64) -> ControlFlow<()>>(&mut self, f: F) {
+        self.escape_hook = Some(Box::new(f));
+    }
+
+    /// Is `pc` outside the program range, see `set_escape_hook`
+    fn escaped(&self, pc: u64) -> bool {
+        self.program_start.map_or(false, |start| pc < start)
+            || self.program_end.map_or(false, |end| pc > end)
+    }
+
+    /// Read the program at `pc`, stopping at the program end
+    ///
+    /// Escaped code, see `set_escape_hook`, is fetched as usual.
+    fn fetch(&self, pc: u64) -> Option<&[u8]> {
+        let data = self.try_target_read(pc)?;
+
+        match self.program_end {
+            Some(end) if pc == end => None,
+            Some(end) if pc < end => data.get(..(end - pc) as usize).or(Some(data)),
+            _ => Some(data),
+        }
+    }
+
+    /// Decode the instruction at the current PC without executing it
+    ///
+    /// Returns the size of the encoded instruction in bytes along with the
+    /// decoded op, so callers managing the PC themselves can find the next
+    /// instruction.
+    pub fn peek_op(&self) -> Result<(u64, Op), DwarfVmError> {
+        let data = self
+            .fetch(self.pc)
+            .ok_or(DwarfVmError::Unmapped { addr: self.pc })?;
+
+        let (sz, op) = decode(data).map_err(|_| decode_error(self.pc, data))?;
+
+        Ok((sz as u64, op))
+    }
+
+    /// Decode the instruction at the current PC if it is an extended op
+    ///
+    /// Returns `None` for the ops handled by `dwarf_dis`, use `peek_op` for
+    /// those.
+    pub fn peek_ext_op(&self) -> Result<Option<(u64, ExtOp)>, DwarfVmError> {
+        let data = self
+            .fetch(self.pc)
+            .ok_or(DwarfVmError::Unmapped { addr: self.pc })?;
+
+        let r = ext::decode(data).map_err(|_| decode_error(self.pc, data))?;
+
+        Ok(r.map(|(sz, op)| (sz as u64, op)))
+    }
+
+    /// Decode either kind of op, returning its size and rendering
+    fn peek_mnemonic(&self) -> Result<(u64, String), DwarfVmError> {
+        if let Some((sz, op)) = self.peek_ext_op()? {
+            return Ok((sz, op.to_string()));
+        }
+
+        let (sz, op) = self.peek_op()?;
+
+        Ok((sz, op.to_string()))
+    }
+
+    /// Render the op at the PC for logging, including decode errors
+    fn state_mnemonic(&self) -> Result<String, DwarfVmError> {
+        match self.peek_mnemonic() {
+            Ok((_, op)) => Ok(op),
+            Err(e @ DwarfVmError::Decode { .. }) => Ok(e.to_string()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get the encoded bytes of the instruction at the current PC
+    ///
+    /// Returns `DwarfVmError::Unmapped` if the instruction runs off the end of
+    /// the region it starts in.
+    pub fn current_op_bytes(&self) -> Result<&[u8], DwarfVmError> {
+        let (sz, _) = self.peek_mnemonic()?;
+
+        let data = self
+            .fetch(self.pc)
+            .ok_or(DwarfVmError::Unmapped { addr: self.pc })?;
+
+        data.get(..sz as usize).ok_or(DwarfVmError::Unmapped {
+            addr: self.pc + data.len() as u64,
+        })
+    }
+
+    /// Get the starting PC of each op in `start..end`
+    ///
+    /// Ops are decoded one after another from `start`, so it must be the
+    /// start of an op. The last op may run past `end`.
+    pub fn op_boundaries(&self, start: u64, end: u64) -> Result<Vec<u64>, DwarfVmError> {
+        let mut pcs = Vec::new();
+        let mut pc = start;
+
+        while pc < end {
+            let data = self
+                .try_target_read(pc)
+                .ok_or(DwarfVmError::Unmapped { addr: pc })?;
+
+            let sz = match ext::decode(data).map_err(|_| decode_error(pc, data))? {
+                Some((sz, _)) => sz,
+                None => decode(data).map_err(|_| decode_error(pc, data))?.0,
+            };
+
+            pcs.push(pc);
+            pc += sz as u64;
+        }
+
+        Ok(pcs)
+    }
+
+    /// Get the net change in stack depth from running the ops in `start..end`
+    ///
+    /// Nothing is executed, each op's fixed effect is summed, see `op_delta`.
+    /// Fails with `DwarfVmError::NoStaticDelta` on a branch.
+    pub fn static_stack_delta(&self, start: u64, end: u64) -> Result<i64, DwarfVmError> {
+        let mut delta = 0;
+        let mut pc = start;
+
+        while pc < end {
+            let data = self
+                .try_target_read(pc)
+                .ok_or(DwarfVmError::Unmapped { addr: pc })?;
+
+            let (sz, d) = match ext::decode(data).map_err(|_| decode_error(pc, data))? {
+                Some((sz, op)) => (sz, ext_op_delta(&op)),
+                None => {
+                    let (sz, op) = decode(data).map_err(|_| decode_error(pc, data))?;
+
+                    (sz, op_delta(&op))
+                }
+            };
+
+            delta += d.ok_or(DwarfVmError::NoStaticDelta { pc })?;
+            pc += sz as u64;
+        }
+
+        Ok(delta)
+    }
+
+    /// Report whether each op in `start..end` can run
+    ///
+    /// Nothing is executed, ops are decoded as by `op_boundaries` and checked
+    /// against the VM's configuration, e.g. `DW_OP_form_tls_address` requires
+    /// `set_tls_base`.
+    pub fn capability_report(
+        &self,
+        start: u64,
+        end: u64,
+    ) -> Result<CapabilityReport, DwarfVmError> {
+        let mut ops = Vec::new();
+        let mut pc = start;
+
+        while pc < end {
+            let data = self
+                .try_target_read(pc)
+                .ok_or(DwarfVmError::Unmapped { addr: pc })?;
+
+            let (sz, cap) = match ext::decode(data).map_err(|_| decode_error(pc, data))? {
+                Some((sz, op)) => (sz, self.ext_capability(&op)),
+                None => {
+                    let (sz, _) = decode(data).map_err(|_| decode_error(pc, data))?;
+
+                    (sz, Capability::Supported)
+                }
+            };
+
+            ops.push((pc, cap));
+            pc += sz as u64;
+        }
+
+        Ok(CapabilityReport { ops })
+    }
+
+    /// Decode the ops in `start..end` once, for repeated `run_compiled` calls
+    ///
+    /// Ops are decoded as by `op_boundaries`. Extended ops, see `ExtOp`, are
+    /// left out and interpreted as usual when run.
+    pub fn compile(&self, start: u64, end: u64) -> Result<CompiledExpr, DwarfVmError> {
+        let mut ops = Vec::new();
+        let mut pc = start;
+
+        while pc < end {
+            let data = self
+                .try_target_read(pc)
+                .ok_or(DwarfVmError::Unmapped { addr: pc })?;
+
+            let sz = match ext::decode(data).map_err(|_| decode_error(pc, data))? {
+                Some((sz, _)) => sz,
+                None => {
+                    let (sz, op) = decode(data).map_err(|_| decode_error(pc, data))?;
+                    ops.push((pc, sz as u64, op));
+
+                    sz
+                }
+            };
+
+            pc += sz as u64;
+        }
+
+        Ok(CompiledExpr { start, end, ops })
+    }
+
+    /// Execute a compiled expression while the PC is in its range
+    ///
+    /// Like `run`, returns the number of instructions executed, stopping early
+    /// at the limit. Compiled ops skip decoding and breakpoints, anything else,
+    /// such as an extended op, a branch into the middle of an op or resuming
+    /// from a breakpoint, is stepped as usual. `DW_OP_call`s are followed out
+    /// of the range until they return. The memory under the expression must
+    /// not change after `compile`.
+    pub fn run_compiled(
+        &mut self,
+        expr: &CompiledExpr,
+        limit: Option<usize>,
+    ) -> Result<usize, DwarfVmError> {
+        let mut ins = 0;
+        let depth = self.call_stack.len();
+
+        while expr.range().contains(&self.pc) || self.call_stack.len() > depth {
+            if let Some(limit) = limit {
+                if ins >= limit {
+                    break;
+                }
+            }
+
+            let r = match expr.ops.binary_search_by_key(&self.pc, |x| x.0) {
+                // only `step` knows to resume over a breakpoint
+                Ok(idx) if self.resume_pc.is_none() => {
+                    let (_, sz, op) = &expr.ops[idx];
+
+                    self.exec(op, *sz)
+                }
+                _ => self.step(),
+            };
+
+            match r {
+                Err(DwarfVmError::Breakpoint { .. }) => return Ok(ins),
+                Err(e) => return Err(e),
+                _ => (),
+            }
+
+            ins += 1;
+        }
+
+        Ok(ins)
+    }
+
+    fn ext_capability(&self, op: &ExtOp) -> Capability {
+        match op {
+            ExtOp::FormTlsAddress if self.tls_base.is_none() => {
+                Capability::RequiresConfig("tls base")
+            }
+            ExtOp::Call(die) if !self.call_targets.contains_key(die) => {
+                Capability::RequiresConfig("call target")
+            }
+            ExtOp::EntryValue(_) | ExtOp::Addrx(_) | ExtOp::Constx(_) => Capability::Unimplemented,
+            ExtOp::ConstType { value, .. } if value.len() > 8 => Capability::Unimplemented,
+            ExtOp::Convert(die) if *die != 0 && !self.base_types.contains_key(die) => {
+                Capability::RequiresConfig("base type")
+            }
+            _ => Capability::Supported,
+        }
+    }
+
+    /// Execute a single Dwarf VM instruction
+    pub fn step(&mut self) -> Result<(), DwarfVmError> {
+        self.fault_in_op();
+
+        if self.decode_resync {
+            self.resync();
+            self.fault_in_op();
+        }
+
+        // ext ops can't be represented as an `Op`, so their breakpoints see a
+        // nop in their place
+        let ext = self.peek_ext_op()?;
+        let (sz, mut op) = match ext {
+            Some(_) => (0, Op::Nop),
+            None => self.peek_op()?,
+        };
+
+        // if we bailed here last time, step over the breakpoint so we can resume
+        let bkpt = if self.resume_pc.take() == Some(self.pc) {
+            None
+        } else {
+            self.breakpoints.remove(&self.pc)
+        };
+
+        if let Some(mut bkpt) = bkpt {
+            let pc = self.pc;
+            let bail = bkpt(self, &mut op);
+
+            self.breakpoints.insert(pc, bkpt);
+
+            if bail {
+                self.resume_pc = Some(self.pc);
+
+                if let Some(e) = self.bkpt_error.take() {
+                    return Err(e);
+                }
+
+                return Err(DwarfVmError::Breakpoint { pc: self.pc });
+            }
+
+            // the breakpoint redirected us, execute from the new PC instead
+            if self.pc != pc {
+                return self.step();
+            }
+        };
+
+        if let Some((sz, op)) = ext {
+            let cost = self.gas_costs.ext;
+            self.charge_gas(cost)?;
+
+            if let ExtOp::Call(die) = op {
+                self.call(die, self.pc + sz)?;
+            } else {
+                self.step_ext(op)?;
+                self.pc += sz;
+            }
+
+            self.return_from_calls();
+            self.reg_location = None;
+            self.insn_count += 1;
+
+            return Ok(());
+        }
+
+        self.exec(&op, sz)
+    }
+
+    /// Execute `op`, `sz` bytes long, at the current PC
+    ///
+    /// Everything but decoding and breakpoints happens here.
+    fn exec(&mut self, op: &Op, sz: u64) -> Result<(), DwarfVmError> {
+        if let Some(mut hook) = self.pre_step.take() {
+            hook(self, op);
+            self.pre_step = Some(hook);
+        }
+
+        let cost = self.gas_costs.cost(op);
+        self.charge_gas(cost)?;
+
+        let op_pc = self.pc;
+        self.pc += sz;
+        self.reg_location = None;
+
+        // set by the nop hook, stopping after the op rather than before it
+        let mut stop = false;
+
+        match *op {
+            Op::Addr(a) => {
+                let a = a
+                    .wrapping_add(self.addr_bias as u64)
+                    .wrapping_add(self.load_bias as u64);
+
+                self.push(a)
+            }
+            Op::Deref => {
+                let t = self.pop();
+                let t = self.strip_ptr(t);
+
+                let v = match self.addr_size {
+                    4 => self.target_read_u32(t)? as u64,
+                    _ => self.target_read_u64(t)?,
+                };
+
+                self.push(v)
+            }
+            Op::Const1u(v) => self.push(v as u64),
+            Op::Const1s(v) => self.push(v as u64),
+            Op::Const2u(v) => self.push(v as u64),
+            Op::Const2s(v) => self.push(v as u64),
+            Op::Const4u(v) => self.push(v as u64),
+            Op::Const4s(v) => self.push(v as u64),
+            Op::Const8u(v) | Op::Constu(v) => self.push(v as u64),
+            Op::Const8s(v) | Op::Consts(v) => self.push(v as u64),
             Op::Dup => {
                 let t = self.pop();
-                self.push(t);
-                self.push(t);
+                self.push(t);
+                self.push(t);
+            }
+            Op::Drop => {
+                let _ = self.pop();
+            }
+            Op::Over => {
+                let t = self.idx(1);
+                self.push(t);
+            }
+            Op::Pick(off) => {
+                self.push(self.idx(off as usize));
+            }
+            Op::Swap => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(p);
+                self.push(q);
+            }
+            Op::Rot => {
+                let x = self.pop();
+                let y = self.pop();
+                let z = self.pop();
+
+                self.push(x);
+                self.push(z);
+                self.push(y);
+            }
+            Op::Abs => {
+                let t = self.pop();
+                self.push(self.sext(t).wrapping_abs() as u64);
+            }
+            Op::And => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(q & p);
+            }
+            Op::Div => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(q / p);
+            }
+            Op::Minus => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let wide = self
+                    .overflow_operand(q)
+                    .checked_sub(self.overflow_operand(p));
+                self.trap_overflow(op_pc, wide)?;
+
+                self.push(q.wrapping_sub(p));
+            }
+            Op::Mod => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(q % p);
+            }
+            Op::Mul => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let wide = self
+                    .overflow_operand(q)
+                    .checked_mul(self.overflow_operand(p));
+                self.trap_overflow(op_pc, wide)?;
+
+                self.push(q.wrapping_mul(p));
+            }
+            Op::Neg => {
+                let t = self.pop();
+
+                self.push(self.sext(t).wrapping_neg() as u64);
             }
-            Op::Drop => {
-                let _ = self.pop();
+            Op::Not => {
+                let t = self.pop();
+
+                self.push(!t);
             }
-            Op::Over => {
-                let t = self.idx(1);
-                self.push(t);
+            Op::Or => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(q | p);
             }
-            Op::Pick(off) => {
-                self.push(self.idx(off as usize));
+            Op::Plus => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let wide = self
+                    .overflow_operand(q)
+                    .checked_add(self.overflow_operand(p));
+                self.trap_overflow(op_pc, wide)?;
+
+                self.push(q.wrapping_add(p));
             }
-            Op::Swap => {
+            Op::PlusConst(v) => {
+                let t = self.pop();
+
+                let wide = self.overflow_operand(t).checked_add(v as i128);
+                self.trap_overflow(op_pc, wide)?;
+
+                self.push(t.wrapping_add(v));
+            }
+            Op::Shl => {
                 let p = self.pop();
                 let q = self.pop();
 
-                self.push(p);
-                self.push(q);
+                self.push(q << p);
             }
-            Op::Rot => {
-                let x = self.pop();
-                let y = self.pop();
-                let z = self.pop();
+            Op::Shr => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(q >> p);
+            }
+            Op::Shra => {
+                let p = self.pop();
+                let q = self.pop();
+
+                // shifting by the width or more just leaves the sign
+                self.push((self.sext(q) >> p.min(63)) as u64);
+            }
+            Op::Xor => {
+                let p = self.pop();
+                let q = self.pop();
+
+                self.push(q ^ p);
+            }
+            Op::Bra(off) => {
+                let taken = self.pop() != 0;
+
+                if taken {
+                    self.pc = self.pc.wrapping_add(off as i64 as u64);
+                }
+
+                self.record_edge(op_pc, taken);
+            }
+            Op::Eq => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let v = self.bool_value(q == p);
+                self.push(v);
+            }
+            Op::Ge => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let v = self.bool_value(q >= p);
+                self.push(v);
+            }
+            Op::Gt => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let v = self.bool_value(q > p);
+                self.push(v);
+            }
+            Op::Le => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let v = self.bool_value(q <= p);
+                self.push(v);
+            }
+            Op::Lt => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let v = self.bool_value(q < p);
+                self.push(v);
+            }
+            Op::Ne => {
+                let p = self.pop();
+                let q = self.pop();
+
+                let v = self.bool_value(q != p);
+                self.push(v);
+            }
+            Op::Skip(off) => {
+                self.pc = self.pc.wrapping_add(off as i64 as u64);
+
+                self.record_edge(op_pc, true);
+            }
+            Op::Lit(v) => self.push(v as u64),
+            Op::Reg(r) => {
+                let v = self.read_reg(r as u64)?;
+
+                self.push(v);
+                self.mark_reg_location(r as u64);
+            }
+            Op::BReg(r, off) => {
+                let v = self.read_reg(r as u64)?;
+
+                // offsets are signed, here and for DW_OP_bregx the cast sign
+                // extends so negative ones subtract
+                self.push(v.wrapping_add(off as u64));
+            }
+            Op::RegX(r) => {
+                let v = self.read_reg(r)?;
+
+                self.push(v);
+                self.mark_reg_location(r);
+            }
+            Op::BRegX(r, off) => {
+                let v = self.read_reg(r as u64)?;
+
+                self.push(v.wrapping_add(off as u64));
+            }
+            Op::DerefSize(sz) => {
+                let t = self.pop();
+                let t = self.strip_ptr(t);
+
+                let v = match sz {
+                    8 => self.target_read_u64(t)?,
+                    4 => self.target_read_u32(t)? as u64,
+                    2 => self.target_read_u16(t)? as u64,
+                    1 => self.target_read_u8(t)? as u64,
+                    _ => panic!(format!("Bad size to DerefSize ({})", sz)),
+                };
+
+                self.push(v);
+            }
+            Op::Nop => {
+                if let Some(mut hook) = self.nop_hook.take() {
+                    stop = hook(self).is_break();
+                    self.nop_hook = Some(hook);
+                }
+            }
+        }
+
+        self.return_from_calls();
+
+        if let Some(mut hook) = self.post_step.take() {
+            hook(self, op);
+            self.post_step = Some(hook);
+        }
+
+        self.insn_count += 1;
+
+        if stop {
+            return Err(DwarfVmError::Breakpoint { pc: self.pc });
+        }
+
+        if self.escaped(self.pc) && !self.escaped(op_pc) {
+            let target = self.pc;
+
+            let flow = match self.escape_hook.take() {
+                Some(mut hook) => {
+                    let flow = hook(self, target);
+                    self.escape_hook = Some(hook);
+
+                    flow
+                }
+                None => return Err(DwarfVmError::BranchOutOfRange { pc: op_pc, target }),
+            };
+
+            if flow.is_break() {
+                return Err(DwarfVmError::Breakpoint { pc: target });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Advance the PC to the next decodable op, see `set_decode_resync`
+    ///
+    /// The PC is left alone if nothing decodes within `RESYNC_LIMIT` bytes.
+    fn resync(&mut self) {
+        let start = self.pc;
+
+        while self.pc - start <= RESYNC_LIMIT {
+            match self.peek_mnemonic() {
+                Err(DwarfVmError::Decode { .. }) => self.pc += 1,
+                Ok(_) => {
+                    let skipped = self.pc - start;
+
+                    if skipped > 0 {
+                        warn!("skipped {} undecodable bytes at {:#x}", skipped, start);
+                        self.resync_skipped += skipped;
+                    }
+
+                    return;
+                }
+                Err(_) => break,
+            }
+        }
+
+        self.pc = start;
+    }
+
+    /// Handle an op the VM doesn't implement, either skipping it or erroring
+    fn unsupported(&mut self, pc: u64, op: &dyn fmt::Display) -> Result<(), DwarfVmError> {
+        if self.skip_unknown {
+            warn!("skipping unsupported op at {:#x} [{}]", pc, op);
+
+            return Ok(());
+        }
+
+        self.pc = pc;
+
+        Err(DwarfVmError::UnsupportedOp { pc })
+    }
+
+    /// Run the expression of DIE `die`, returning to `ret`, see
+    /// `add_call_target`
+    fn call(&mut self, die: u64, ret: u64) -> Result<(), DwarfVmError> {
+        let (start, end) = match self.call_targets.get(&die) {
+            Some(&target) => target,
+            None => {
+                self.unsupported(self.pc, &ExtOp::Call(die))?;
+                self.pc = ret;
+
+                return Ok(());
+            }
+        };
+
+        if self
+            .max_call_depth
+            .map_or(false, |n| self.call_stack.len() >= n)
+        {
+            return Err(DwarfVmError::CallDepthExceeded { pc: self.pc });
+        }
+
+        self.call_stack.push((ret, end));
+        self.pc = start;
+
+        Ok(())
+    }
+
+    /// Return from any calls whose expression the PC just reached the end of
+    ///
+    /// This happens in the step which reaches the end, so the PC never rests
+    /// past a callee.
+    fn return_from_calls(&mut self) {
+        while let Some(&(ret, end)) = self.call_stack.last() {
+            if self.pc != end {
+                break;
+            }
+
+            self.pc = ret;
+            self.call_stack.pop();
+        }
+    }
+
+    fn step_ext(&mut self, op: ExtOp) -> Result<(), DwarfVmError> {
+        match op {
+            ExtOp::Call(_) => unreachable!("calls are handled by step"),
+            ExtOp::FormTlsAddress => {
+                let base = self
+                    .tls_base
+                    .ok_or(DwarfVmError::NoTlsBase { pc: self.pc })?;
+                let t = self.pop();
+
+                self.push(base.wrapping_add(t));
+            }
+            ExtOp::EntryValue(_) | ExtOp::Addrx(_) | ExtOp::Constx(_) => {
+                self.unsupported(self.pc, &op)?;
+            }
+            ExtOp::ImplicitValue(v) => {
+                self.record(VmEvent::Implicit(v.clone()));
+
+                self.implicit = Some(v);
+            }
+            ExtOp::StackValue => self.stack_value = true,
+            ExtOp::Piece(size) => {
+                let size = size.checked_mul(8).ok_or(DwarfVmError::CompositeTooLarge)?;
+
+                self.push_piece(size, 0)?;
+            }
+            ExtOp::BitPiece { size, offset } => self.push_piece(size, offset)?,
+            ExtOp::ConstType { value, .. } => {
+                if value.len() > 8 {
+                    return Err(DwarfVmError::UnsupportedTypedOp { pc: self.pc });
+                }
+
+                let mut buf = [0u8; 8];
+                buf[..value.len()].copy_from_slice(&value);
+
+                self.push(u64::from_le_bytes(buf));
+            }
+            ExtOp::Convert(die) => {
+                let (size, signed) = match die {
+                    0 => (self.generic_type_size(), false),
+                    _ => *self
+                        .base_types
+                        .get(&die)
+                        .ok_or(DwarfVmError::UnsupportedTypedOp { pc: self.pc })?,
+                };
+
+                if ![1, 2, 4, 8].contains(&size) {
+                    return Err(DwarfVmError::UnsupportedTypedOp { pc: self.pc });
+                }
+
+                let shift = 64 - size as u32 * 8;
+                let v = self.pop() << shift;
+
+                if signed {
+                    self.push(((v as i64) >> shift) as u64);
+                } else {
+                    self.push(v >> shift);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Note a register op which may be a whole register location
+    ///
+    /// Only a register op run on an empty stack names a location, see
+    /// `ExprResult::Register`.
+    fn mark_reg_location(&mut self, reg: u64) {
+        if self.stack.len() == 1 {
+            self.reg_location = Some(reg);
+        }
+    }
+
+    /// End the location description so far, recording it as a piece
+    ///
+    /// Fails, recording nothing, if the composite would grow past
+    /// `MAX_COMPOSITE_SIZE`.
+    fn push_piece(&mut self, size: u64, offset: u64) -> Result<(), DwarfVmError> {
+        add_piece_bits(composite_bits(&self.pieces)?, size, offset)?;
+
+        let location = if let Some(v) = self.implicit.take() {
+            PieceLocation::Implicit(v)
+        } else if let Some(reg) = self.reg_location.take() {
+            PieceLocation::Register {
+                reg,
+                value: self.pop(),
+            }
+        } else if self.stack.is_empty() {
+            PieceLocation::Empty
+        } else if self.stack_value {
+            PieceLocation::Value(self.pop())
+        } else {
+            PieceLocation::Memory(self.pop())
+        };
+
+        self.stack_value = false;
+
+        self.pieces.push(Piece {
+            size,
+            offset,
+            location,
+        });
+
+        Ok(())
+    }
+
+    /// Get the pieces of a composite location, in order
+    pub fn pieces(&self) -> &[Piece] {
+        &self.pieces
+    }
+
+    /// Assemble the pieces of a composite location into the value's bytes
+    ///
+    /// Each piece's bits are concatenated in order, memory pieces are read
+    /// from the target. Fails with `DwarfVmError::OptimizedOut` if a piece has
+    /// no location, or with `DwarfVmError::CompositeTooLarge` if the pieces
+    /// add up to more than `MAX_COMPOSITE_SIZE` bytes.
+    pub fn assemble_pieces(&self) -> Result<Vec<u8>, DwarfVmError> {
+        let total = composite_bits(&self.pieces)?;
+        let mut out = vec![0u8; ((total + 7) / 8) as usize];
+        let mut pos = 0;
+
+        for (idx, piece) in self.pieces.iter().enumerate() {
+            // checked by composite_bits
+            let len = ((piece.offset + piece.size + 7) / 8) as usize;
+
+            let src = match &piece.location {
+                PieceLocation::Memory(a) => self
+                    .try_target_read(*a)
+                    .and_then(|x| x.get(..len))
+                    .ok_or(DwarfVmError::Unmapped { addr: *a })?
+                    .to_vec(),
+                PieceLocation::Value(v) | PieceLocation::Register { value: v, .. } => {
+                    v.to_le_bytes().to_vec()
+                }
+                PieceLocation::Implicit(v) => v.clone(),
+                PieceLocation::Empty => return Err(DwarfVmError::OptimizedOut { piece: idx }),
+            };
+
+            for ii in 0..piece.size {
+                let bit = piece.offset + ii;
+                let set = src
+                    .get((bit / 8) as usize)
+                    .map_or(false, |x| x >> (bit % 8) & 1 == 1);
+
+                if set {
+                    out[(pos / 8) as usize] |= 1 << (pos % 8);
+                }
+
+                pos += 1;
+            }
+        }
+
+        Ok(out)
+    }
+
+    /// Get the result of the expression
+    ///
+    /// Returns `None` if the stack is empty and no implicit value was seen.
+    pub fn result(&self) -> Option<ExprResult> {
+        if let Some(v) = &self.implicit {
+            return Some(ExprResult::ImplicitBytes(v.clone()));
+        }
+
+        let top = *self.stack.last()?;
+
+        if let (Some(reg), false) = (self.reg_location, self.stack_value) {
+            return Some(ExprResult::Register(reg));
+        }
+
+        if self.stack_value {
+            return Some(ExprResult::Value(top));
+        }
+
+        Some(ExprResult::Address(top))
+    }
+
+    /// Get the result of the expression, checking it is of the expected kind
+    ///
+    /// Fails with `DwarfVmError::NotAnAddress` when an address was expected
+    /// but the expression ended in `DW_OP_stack_value` or
+    /// `DW_OP_implicit_value`, and `DwarfVmError::NotAValue` the other way
+    /// round. Returns `DwarfVmError::UnbalancedStack` if there is no result.
+    pub fn finalize(&self, kind: ExpectedResult) -> Result<ExprResult, DwarfVmError> {
+        let r = self
+            .result()
+            .ok_or(DwarfVmError::UnbalancedStack { remaining: 0 })?;
+
+        match (kind, &r) {
+            (ExpectedResult::Address, ExprResult::Address(_))
+            | (ExpectedResult::Address, ExprResult::Register(_)) => Ok(r),
+            (ExpectedResult::Address, _) => Err(DwarfVmError::NotAnAddress),
+            (ExpectedResult::Value, ExprResult::Address(_))
+            | (ExpectedResult::Value, ExprResult::Register(_)) => Err(DwarfVmError::NotAValue),
+            (ExpectedResult::Value, _) => Ok(r),
+        }
+    }
+
+    /// Execute a single Dwarf VM instruction, recording what it did
+    ///
+    /// Returns the PC, size and rendering of the op along with its effects.
+    fn step_recorded(&mut self) -> Result<(u64, u64, String, Vec<VmEvent>), DwarfVmError> {
+        let pc = self.pc;
+        let (sz, op) = self.peek_mnemonic()?;
+
+        self.events = Some(Vec::new());
+        let r = self.step();
+        let events = self.events.take().unwrap_or_default();
+        r?;
+
+        Ok((pc, sz, op, events))
+    }
+
+    /// Execute a single Dwarf VM instruction, describing what it did
+    ///
+    /// Returns a sentence such as `"deref: popped 0x7fffffe110, read 8 bytes
+    /// at 0x7fffffe110 = 0x42, pushed 0x42"` along with the resulting state.
+    pub fn explain_step(&mut self) -> Result<(String, DwarfVmState), DwarfVmError> {
+        let (pc, sz, op, events) = self.step_recorded()?;
+        let next = pc + sz;
+
+        let mut parts = events
+            .iter()
+            .map(|e| match e {
+                VmEvent::Push(v) => format!("pushed {:#x}", v),
+                VmEvent::Pop(v) => format!("popped {:#x}", v),
+                VmEvent::Read(r) if r.synthetic => format!(
+                    "read {} bytes at unmapped {:#x} = {:#x} (synthetic)",
+                    r.size, r.addr, r.value
+                ),
+                VmEvent::Read(r) => {
+                    format!("read {} bytes at {:#x} = {:#x}", r.size, r.addr, r.value)
+                }
+                VmEvent::Implicit(v) => format!("value is {:02x?}", v),
+            })
+            .collect::<Vec<_>>();
+
+        if self.pc != next {
+            parts.push(format!("jumped to {:#x}", self.pc));
+        }
+
+        let desc = if parts.is_empty() {
+            op
+        } else {
+            format!("{}: {}", op, parts.join(", "))
+        };
+
+        Ok((desc, self.state()))
+    }
+
+    /// Execute a single Dwarf VM instruction, returning what it did
+    ///
+    /// This is a pull based alternative to watching `explain_step` output or
+    /// installing breakpoints, suitable for driving a REPL.
+    pub fn step_event(&mut self) -> Result<StepEvent, DwarfVmError> {
+        let (pc, _, op, events) = self.step_recorded()?;
+
+        let mut event = StepEvent {
+            pc,
+            op,
+            reads: Vec::new(),
+            popped: Vec::new(),
+            pushed: Vec::new(),
+            next_pc: self.pc,
+        };
 
-                self.push(x);
-                self.push(z);
-                self.push(y);
-            }
-            Op::Abs => {
-                let t = self.pop() as i64;
-                self.push(t.abs() as u64);
+        for e in events {
+            match e {
+                VmEvent::Push(v) => event.pushed.push(v),
+                VmEvent::Pop(v) => event.popped.push(v),
+                VmEvent::Read(r) => event.reads.push(r),
+                VmEvent::Implicit(_) => (),
             }
-            Op::And => {
-                let p = self.pop();
-                let q = self.pop();
+        }
 
-                self.push(q & p);
-            }
-            Op::Div => {
-                let p = self.pop();
-                let q = self.pop();
+        Ok(event)
+    }
 
-                self.push(q / p);
-            }
-            Op::Minus => {
-                let p = self.pop();
-                let q = self.pop();
+    /// Execute many Dwarf VM instructions
+    ///
+    /// Returns the number of instructions executed by this call, stopping
+    /// early if a breakpoint bails. Calling `run` again resumes from the
+    /// breakpoint, see `insn_count` for a total across calls.
+    pub fn run(&mut self, limit: Option<usize>) -> Result<usize, DwarfVmError> {
+        self.run_until(limit, |_| false)
+    }
 
-                self.push(q.wrapping_sub(p));
-            }
-            Op::Mod => {
-                let p = self.pop();
-                let q = self.pop();
+    /// Execute Dwarf VM instructions until the stack holds `depth` entries
+    ///
+    /// Like `run`, returns the number of instructions executed, stopping early
+    /// at the limit or if a breakpoint bails. Returns immediately if the stack
+    /// is already at `depth`.
+    pub fn run_until_depth(
+        &mut self,
+        depth: usize,
+        limit: Option<usize>,
+    ) -> Result<usize, DwarfVmError> {
+        self.run_until(limit, |dvm| dvm.stack.len() == depth)
+    }
 
-                self.push(q % p);
-            }
-            Op::Mul => {
-                let p = self.pop();
-                let q = self.pop();
+    /// Execute Dwarf VM instructions until `pred` holds, returning the PC
+    ///
+    /// `pred` is checked before each instruction, so the VM is left at the
+    /// first state matching it. Returns `None` if the limit is reached or a
+    /// breakpoint bails first.
+    pub fn find_step<F: Fn(&Self) -> bool>(
+        &mut self,
+        pred: F,
+        limit: Option<usize>,
+    ) -> Result<Option<u64>, DwarfVmError> {
+        self.run_until(limit, &pred)?;
 
-                self.push(q.wrapping_mul(p));
-            }
-            Op::Neg => {
-                let t = self.pop();
+        Ok(Some(self.pc).filter(|_| pred(self)))
+    }
 
-                self.push(-(t as i64) as u64);
+    /// Execute until `stop` returns true, checking it before each instruction
+    fn run_until<F: FnMut(&Self) -> bool>(
+        &mut self,
+        limit: Option<usize>,
+        mut stop: F,
+    ) -> Result<usize, DwarfVmError> {
+        let mut ins = 0;
+        loop {
+            if stop(self) {
+                return Ok(ins);
             }
-            Op::Not => {
-                let t = self.pop();
 
-                self.push(!t);
+            if let Some(limit) = limit {
+                if ins >= limit {
+                    return Ok(ins);
+                }
             }
-            Op::Or => {
-                let p = self.pop();
-                let q = self.pop();
 
-                self.push(q | p);
+            if let Some(depth) = self.trace_depth {
+                let _ = self.trace_state(depth);
             }
-            Op::Plus => {
-                let p = self.pop();
-                let q = self.pop();
 
-                self.push(q.wrapping_add(p));
+            match self.step() {
+                Err(DwarfVmError::Breakpoint { .. }) => return Ok(ins),
+                Err(e) => return Err(e),
+                _ => (),
             }
-            Op::PlusConst(v) => {
-                let t = self.pop();
 
-                self.push(t.wrapping_add(v));
-            }
-            Op::Shl => {
-                let p = self.pop();
-                let q = self.pop();
+            ins += 1;
+        }
+    }
 
-                self.push(q << p);
+    /// Execute many Dwarf VM instructions, recording the state before each
+    ///
+    /// Recording stops at the limit or at the first step which fails, that
+    /// step is not recorded and its error is dropped.
+    pub fn record_trace(&mut self, limit: Option<usize>) -> Vec<TraceState> {
+        let mut trace = Vec::new();
+
+        while limit.map_or(true, |limit| trace.len() < limit) {
+            let start = self.stack.len().saturating_sub(TRACE_STACK_DEPTH);
+
+            let state = TraceState {
+                pc: self.pc,
+                stack_sz: self.stack.len(),
+                stack: self.stack[start..].to_vec(),
+            };
+
+            if self.step().is_err() {
+                break;
             }
-            Op::Shr => {
-                let p = self.pop();
-                let q = self.pop();
 
-                self.push(q >> p);
-            }
-            Op::Shra => {
-                let p = self.pop();
-                let q = self.pop();
+            trace.push(state);
+        }
 
-                self.push(q >> p);
+        trace
+    }
+
+    /// Execute many Dwarf VM instructions, summarizing the run
+    ///
+    /// Like `run`, but also stops cleanly when the PC leaves mapped memory and
+    /// reports why it stopped rather than returning errors. The counts only
+    /// cover this call.
+    pub fn run_summary(&mut self, limit: Option<usize>) -> RunSummary {
+        let insn_count = self.insn_count;
+        let reads = self.reads;
+        let max_stack_depth = self.max_stack_depth;
+
+        self.max_stack_depth = self.stack.len();
+
+        let stop_reason = match self.run_until(limit, |dvm| dvm.pc_unmapped()) {
+            Err(e) => StopReason::Error(e),
+            Ok(_) if self.pc_unmapped() => StopReason::ProgramEnd,
+            Ok(n) if Some(n) == limit => StopReason::Limit,
+            Ok(_) => StopReason::Breakpoint,
+        };
+
+        let summary = RunSummary {
+            instructions: (self.insn_count - insn_count) as usize,
+            reads: self.reads - reads,
+            max_stack: self.max_stack_depth,
+            stop_reason,
+        };
+
+        self.max_stack_depth = self.max_stack_depth.max(max_stack_depth);
+
+        summary
+    }
+
+    fn pc_unmapped(&self) -> bool {
+        self.program_end == Some(self.pc) || !self.is_mapped(self.pc, 1)
+    }
+
+    /// Execute many Dwarf VM instructions, returning the top of the stack
+    ///
+    /// Returns `DwarfVmError::UnbalancedStack` if the stack is empty afterwards.
+    pub fn run_to_result(&mut self, limit: Option<usize>) -> Result<u64, DwarfVmError> {
+        self.run(limit)?;
+
+        self.stack
+            .last()
+            .copied()
+            .ok_or(DwarfVmError::UnbalancedStack { remaining: 0 })
+    }
+
+    /// Execute many Dwarf VM instructions, returning the address computed
+    ///
+    /// Unlike `run_to_result`, fails with `DwarfVmError::NotAnAddress` if the
+    /// expression produced a value rather than the value's location, via
+    /// `DW_OP_stack_value` or `DW_OP_implicit_value`, or if the value is in a
+    /// register.
+    pub fn eval_address(&mut self, limit: Option<usize>) -> Result<u64, DwarfVmError> {
+        self.run(limit)?;
+
+        match self.finalize(ExpectedResult::Address)? {
+            ExprResult::Address(a) => Ok(a),
+            _ => Err(DwarfVmError::NotAnAddress),
+        }
+    }
+
+    /// Execute an expression to completion, returning its typed result
+    ///
+    /// Expressions which built pieces give `Evaluation::Composite`, otherwise
+    /// the result is as for `result`, checked against `cfg.expect` if set.
+    /// Returns `DwarfVmError::UnbalancedStack` if there is no result.
+    pub fn evaluate(&mut self, cfg: EvalConfig) -> Result<Evaluation, DwarfVmError> {
+        self.run(cfg.limit)?;
+
+        if !self.pieces.is_empty() {
+            return Ok(Evaluation::Composite(self.pieces.clone()));
+        }
+
+        let r = match cfg.expect {
+            Some(kind) => self.finalize(kind)?,
+            None => self
+                .result()
+                .ok_or(DwarfVmError::UnbalancedStack { remaining: 0 })?,
+        };
+
+        Ok(r.into())
+    }
+
+    /// Evaluate many expressions against the same core
+    ///
+    /// Each job is a `(pc, ctx, limit)` tuple. For each job the VM is reset to
+    /// `pc` with the given context, run for at most `limit` instructions, and
+    /// the top of the stack collected. The overlay and breakpoints are shared
+    /// between jobs, and the VM is left in the state of the last job.
+    pub fn batch_eval(&mut self, jobs: &[(u64, u64, usize)]) -> Vec<Result<u64, DwarfVmError>> {
+        jobs.iter()
+            .map(|&(pc, ctx, limit)| {
+                self.entry = pc;
+                self.reset();
+                self.set_ctx(ctx);
+
+                self.run_to_result(Some(limit))
+            })
+            .collect()
+    }
+
+    /// Check that exactly one value, the result, is left on the stack
+    ///
+    /// Call this after a run; a well formed location expression always
+    /// terminates with a single value.
+    pub fn check_balanced(&self) -> Result<(), DwarfVmError> {
+        if self.stack.len() != 1 {
+            warn!("unbalanced stack, {} values remaining", self.stack.len());
+
+            return Err(DwarfVmError::UnbalancedStack {
+                remaining: self.stack.len(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Step the VM, checking each resulting state against a recorded trace
+    ///
+    /// `states[n]` is the expected state after the `n + 1`th step. Stops at the
+    /// first divergence. `states` may be full `DwarfVmState`s or golden
+    /// `TraceState`s.
+    pub fn verify_trace<S: ExpectedState>(&mut self, states: &[S]) -> Result<(), TraceMismatch> {
+        for (index, expected) in states.iter().enumerate() {
+            if let Err(e) = self.step() {
+                return Err(TraceMismatch {
+                    index,
+                    diff: StateDiff::Error(e),
+                });
             }
-            Op::Xor => {
-                let p = self.pop();
-                let q = self.pop();
 
-                self.push(q ^ p);
+            if let Some(diff) = expected.diff(&self.state()) {
+                return Err(TraceMismatch { index, diff });
             }
-            Op::Bra(off) => {
-                if self.pop() != 0 {
-                    self.pc = self.pc.wrapping_add(off as i64 as u64);
+        }
+
+        Ok(())
+    }
+
+    /// Step the VM, checking each branch against a recorded edge trace
+    ///
+    /// `edges` is a trace from `edge_trace`. Stepping stops once every edge has
+    /// been matched, failing with `DwarfVmError::TraceDivergence` at the first
+    /// branch which went elsewhere. This enables the edge trace.
+    pub fn replay_edges(&mut self, edges: &[(u64, u64, bool)]) -> Result<(), DwarfVmError> {
+        self.enable_edge_trace();
+
+        let mut index = 0;
+        let mut seen = self.edge_trace().len();
+
+        while index < edges.len() {
+            self.step()?;
+
+            for edge in &self.edge_trace()[seen..] {
+                if *edge != edges[index] {
+                    return Err(DwarfVmError::TraceDivergence { index });
                 }
-            }
-            Op::Eq => {
-                let p = self.pop();
-                let q = self.pop();
 
-                self.push(u64::from(q == p));
+                index += 1;
             }
-            Op::Ge => {
-                let p = self.pop();
-                let q = self.pop();
 
-                self.push(u64::from(q >= p));
-            }
-            Op::Gt => {
-                let p = self.pop();
-                let q = self.pop();
+            seen = self.edge_trace().len();
+        }
 
-                self.push(u64::from(q > p));
-            }
-            Op::Le => {
-                let p = self.pop();
-                let q = self.pop();
+        Ok(())
+    }
 
-                self.push(u64::from(q <= p));
-            }
-            Op::Lt => {
-                let p = self.pop();
-                let q = self.pop();
+    /// Dump the current DwarfVM state
+    ///
+    /// This avoids dumping the context structure and coredump, as these are
+    /// immutable once emulation starts and would add significant overhead.
+    pub fn state(&self) -> DwarfVmState {
+        DwarfVmState {
+            pc: self.pc,
+            stack: self.stack.clone(),
+        }
+    }
+
+    /// Replace the stack, top of stack last
+    ///
+    /// Nothing about the contents is checked. With the PC this is enough to
+    /// start executing at any op, e.g. a `DW_OP_bra` under test.
+    pub fn set_stack(&mut self, stack: Vec<u64>) {
+        // keep the buffer, see `with_stack_capacity`
+        self.stack.clear();
+        self.stack.extend(stack);
+    }
+
+    /// Get the stack entry `index_from_bottom` entries from the bottom
+    ///
+    /// Unlike `DW_OP_pick`, which counts from the top, this addresses entries
+    /// by absolute position, e.g. arguments seeded at the bottom of the stack.
+    pub fn pick_abs(&self, index_from_bottom: usize) -> Option<u64> {
+        self.stack.get(index_from_bottom).copied()
+    }
+
+    /// Dump the PC and only the top `n` stack entries, bottom to top
+    ///
+    /// This is for observation only, the result is not meaningful to pass to
+    /// `set_state` as the rest of the stack is dropped.
+    pub fn top_state(&self, n: usize) -> DwarfVmState {
+        let start = self.stack.len().saturating_sub(n);
+
+        DwarfVmState {
+            pc: self.pc,
+            stack: self.stack[start..].to_vec(),
+        }
+    }
+
+    /// Load a DwarfVM state
+    pub fn set_state(&mut self, state: &DwarfVmState) {
+        self.pc = state.pc;
+        self.stack.clear();
+        self.stack.extend_from_slice(&state.stack);
+    }
+
+    /// Dump the current memory overlay
+    ///
+    /// Unlike `state`, this only captures the overlay, so the stack and PC are
+    /// left alone when it is restored.
+    pub fn overlay_snapshot(&self) -> OverlaySnapshot {
+        OverlaySnapshot {
+            overlay: self.overlay.clone(),
+        }
+    }
+
+    /// Load a memory overlay snapshot
+    pub fn restore_overlay(&mut self, snap: &OverlaySnapshot) {
+        self.overlay = snap.overlay.clone();
+    }
+
+    /// Capture the VM so it can be restored onto another core
+    ///
+    /// This captures the same parts of the VM as `save`, see `restore_onto`.
+    pub fn checkpoint(&self) -> VmCheckpoint {
+        VmCheckpoint {
+            snap: self.snapshot(),
+        }
+    }
+
+    /// Create a VM from a checkpoint over `core`
+    ///
+    /// `core` need not be the core the checkpoint was taken over, only one
+    /// with the same layout, such as a later dump of the same process. The
+    /// stack and overlay come from the checkpoint, other reads go to `core`.
+    /// Fails with `DwarfVmError::Unmapped` if the PC is mapped in neither.
+    pub fn restore_onto(cp: &VmCheckpoint, core: &'a [u8]) -> Result<Self, DwarfVmError> {
+        let dvm = Self::from_snapshot(cp.snap.clone(), core)?;
+
+        if !dvm.is_mapped(dvm.pc, 1) {
+            return Err(DwarfVmError::Unmapped { addr: dvm.pc });
+        }
+
+        Ok(dvm)
+    }
+
+    /// Save the VM to `w`, see `load`
+    ///
+    /// The PC, entry, context, instruction count, address size, stack and
+    /// overlay are saved. The core is not, it must be supplied again to
+    /// `load`. Other configuration, breakpoints and hooks are dropped.
+    pub fn save<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        snapshot::write(w, &self.snapshot())
+    }
+
+    /// Load a VM saved by `save` over `core`
+    ///
+    /// Fails with `DwarfVmError::BadSnapshot` if `r` doesn't hold a saved VM.
+    pub fn load<R: Read>(r: &mut R, core: &'a [u8]) -> Result<Self, DwarfVmError> {
+        Self::from_snapshot(snapshot::read(r)?, core)
+    }
+
+    /// Capture the parts of the VM shared by `save` and `checkpoint`
+    fn snapshot(&self) -> snapshot::Snapshot {
+        snapshot::Snapshot {
+            pc: self.pc,
+            entry: self.entry,
+            ctx: self.ctx,
+            insn_count: self.insn_count,
+            addr_size: self.addr_size,
+            stack: self.stack.clone(),
+            overlay: self.overlay.clone(),
+        }
+    }
+
+    /// Rebuild a VM over `core` for `load` and `restore_onto`
+    fn from_snapshot(snap: snapshot::Snapshot, core: &'a [u8]) -> Result<Self, DwarfVmError> {
+        if snap.addr_size != 4 && snap.addr_size != 8 {
+            return Err(DwarfVmError::BadSnapshot);
+        }
+
+        let mut dvm = Self::try_new(snap.pc, snap.ctx, core)?;
+        dvm.entry = snap.entry;
+        dvm.insn_count = snap.insn_count;
+        dvm.addr_size = snap.addr_size;
+        dvm.stack = snap.stack;
+        dvm.overlay = snap.overlay;
+
+        Ok(dvm)
+    }
+
+    /// Limit register ops to registers `0..n`
+    ///
+    /// Register numbers past this fail with `DwarfVmError::BadRegister` rather
+    /// than reading past the end of the context's register array. By default
+    /// any register number is allowed.
+    pub fn set_register_count(&mut self, n: u64) {
+        self.reg_count = Some(n);
+    }
+
+    /// Install a callback used to fetch register values
+    ///
+    /// Registers are resolved from the first available source:
+    ///
+    /// 1. the register provider, if one is installed
+    /// 2. the context structure in target memory
+    ///
+    /// An installed provider answers every register read, its errors are
+    /// returned from `step` as is.
+    pub fn set_register_provider<F: 'static + FnMut(u64) -> Result<u64, DwarfVmError>>(
+        &mut self,
+        f: F,
+    ) {
+        self.reg_provider = Some(Box::new(f));
+    }
+
+    /// Read register `n` as `DW_OP_reg` would, reporting where it came from
+    ///
+    /// The register provider takes precedence over the context structure.
+    /// Takes `&mut self` as the provider may be stateful. The context is read
+    /// as by `read_with_source`, so inspecting a register doesn't charge the
+    /// read budget or count towards `reads`.
+    pub fn effective_reg(&mut self, n: u64) -> Result<(u64, RegSource), DwarfVmError> {
+        if let Some(v) = self.provided_reg(n)? {
+            return Ok((v, RegSource::Provider));
+        }
+
+        let p = self.peek_u64(self.reg_slot(n)?)?;
+        let v = self.peek_u64(self.strip_ptr(p))?;
+
+        Ok((v, RegSource::Context))
+    }
+
+    /// Remove the register provider, falling back to the context structure
+    pub fn clear_register_provider(&mut self) {
+        self.reg_provider = None;
+    }
+
+    /// Install a callback called for every value pushed or popped by an op
+    ///
+    /// Stack manipulation from outside the VM, e.g. `set_state`, isn't seen.
+    pub fn set_stack_observer<F: 'static + FnMut(StackEvent)>(&mut self, f: F) {
+        self.stack_observer = Some(Box::new(f));
+    }
+
+    /// Install a callback called when a typed read runs off the end of the
+    /// overlay into the core
+    ///
+    /// `f` is given the address, the number of bytes the overlay covers and
+    /// the size of the read. Such reads are not stitched together, so this is
+    /// for auditing overlay coverage, e.g. a patch covering only part of a
+    /// structure.
+    pub fn set_boundary_read_hook<F: 'static + FnMut(u64, usize, usize)>(&mut self, f: F) {
+        self.boundary_read_hook = Some(Box::new(f));
+    }
+
+    /// Install a hook called before each op executes
+    ///
+    /// The hook sees the VM with the PC at the op, after any breakpoint has
+    /// run. Extended ops, see `ExtOp`, don't call the hooks.
+    pub fn set_pre_step<F: 'static + FnMut(&Self, &Op)>(&mut self, f: F) {
+        self.pre_step = Some(Box::new(f));
+    }
+
+    /// Install a hook called after each op executes
+    ///
+    /// The hook sees the VM with the PC at the next op. It is not called if
+    /// the op fails. Extended ops, see `ExtOp`, don't call the hooks.
+    pub fn set_post_step<F: 'static + FnMut(&Self, &Op)>(&mut self, f: F) {
+        self.post_step = Some(Box::new(f));
+    }
+
+    fn record_edge(&mut self, from: u64, taken: bool) {
+        let to = self.pc;
+
+        if let Some(edges) = &mut self.edges {
+            edges.push((from, to, taken));
+        }
+    }
+
+    /// Strip pointer authentication bits from a value used as an address
+    fn strip_ptr(&self, a: u64) -> u64 {
+        a & !self.ptr_auth_mask
+    }
 
-                self.push(u64::from(q < p));
-            }
-            Op::Ne => {
-                let p = self.pop();
-                let q = self.pop();
+    fn read_reg(&mut self, r: u64) -> Result<u64, DwarfVmError> {
+        Ok(self.resolve_reg(r)?.0)
+    }
 
-                self.push(u64::from(q != p));
-            }
-            Op::Skip(off) => self.pc = self.pc.wrapping_add(off as i64 as u64),
-            Op::Lit(v) => self.push(v as u64),
-            Op::Reg(r) => {
-                let p = self.target_read_u64(self.ctx + r as u64 * 8);
-                let q = self.target_read_u64(p);
+    /// Read register `r` as the register ops do, along with where it came from
+    fn resolve_reg(&mut self, r: u64) -> Result<(u64, RegSource), DwarfVmError> {
+        if let Some(v) = self.provided_reg(r)? {
+            return Ok((v, RegSource::Provider));
+        }
 
-                self.push(q);
-            }
-            Op::BReg(_, _) => todo!(),
-            Op::RegX(_) => todo!(),
-            Op::BRegX(_, _) => todo!(),
-            Op::DerefSize(sz) => {
-                let t = self.pop();
+        let p = self.target_read_u64(self.reg_slot(r)?)?;
+        let v = self.target_read_u64(self.strip_ptr(p))?;
 
-                let v = match sz {
-                    8 => self.target_read_u64(t),
-                    4 => self.target_read_u32(t) as u64,
-                    2 => self.target_read_u16(t) as u64,
-                    1 => self.target_read_u8(t) as u64,
-                    _ => panic!(format!("Bad size to DerefSize ({})", sz)),
-                };
+        Ok((v, RegSource::Context))
+    }
 
-                self.push(v);
-            }
-            Op::Nop => (),
+    /// Check `r` is in range, then ask the register provider for it if there
+    /// is one
+    fn provided_reg(&mut self, r: u64) -> Result<Option<u64>, DwarfVmError> {
+        if self.reg_count.map_or(false, |n| r >= n) {
+            return Err(DwarfVmError::BadRegister { reg: r });
         }
 
-        Ok(())
+        match &mut self.reg_provider {
+            Some(f) => f(r).map(Some),
+            None => Ok(None),
+        }
     }
 
-    /// Execute many Dwarf VM instructions
-    pub fn run(&mut self, limit: Option<usize>) -> Result<usize, DwarfVmError> {
-        let mut ins = 0;
-        loop {
-            if let Some(limit) = limit {
-                if ins >= limit {
-                    return Ok(ins);
-                }
-            }
+    /// Get the address of register `r`'s slot in the context structure
+    fn reg_slot(&self, r: u64) -> Result<u64, DwarfVmError> {
+        // the context holds an array of pointers to the saved registers, don't
+        // let a huge register number wrap around to unrelated memory
+        r.checked_mul(8)
+            .and_then(|x| self.ctx.checked_add(x))
+            .ok_or(DwarfVmError::BadRegister { reg: r })
+    }
 
-            let _ = self.trace_state(3);
+    /// Read a u64 at `a` without charging or recording it, see
+    /// `read_with_source`
+    fn peek_u64(&self, a: u64) -> Result<u64, DwarfVmError> {
+        let (data, _) = self.read_with_source(a, 8)?;
 
-            match self.step() {
-                Err(DwarfVmError::Breakpoint) => return Ok(ins),
-                Err(e) => return Err(e),
-                _ => (),
-            }
+        Ok(u64::from_le_bytes(data[..].try_into().unwrap()))
+    }
 
-            ins += 1;
+    fn record(&mut self, event: VmEvent) {
+        if let Some(events) = &mut self.events {
+            events.push(event);
         }
     }
 
-    /// Dump the current DwarfVM state
+    /// Widen an operand for overflow checking, see `set_signed_overflow`
+    fn overflow_operand(&self, v: u64) -> i128 {
+        if self.signed_overflow {
+            self.sext(v) as i128
+        } else {
+            v as i128
+        }
+    }
+
+    /// Fail if `wide`, a result computed from `overflow_operand`s, doesn't
+    /// fit the generic type
     ///
-    /// This avoids dumping the context structure and coredump, as these are
-    /// immutable once emulation starts and would add significant overhead.
-    pub fn state(&self) -> DwarfVmState {
-        DwarfVmState {
-            pc: self.pc,
-            stack: self.stack.clone(),
+    /// Only checked with `set_overflow_trap`, `None` means the result didn't
+    /// even fit an i128.
+    fn trap_overflow(&self, pc: u64, wide: Option<i128>) -> Result<(), DwarfVmError> {
+        let mask = self.generic_mask() as i128;
+
+        let (min, max) = if self.signed_overflow {
+            (-(mask >> 1) - 1, mask >> 1)
+        } else {
+            (0, mask)
+        };
+
+        if self.overflow_trap && wide.map_or(true, |v| v < min || v > max) {
+            return Err(DwarfVmError::ArithmeticOverflow { pc });
         }
+
+        Ok(())
     }
 
-    /// Load a DwarfVM state
-    pub fn set_state(&mut self, state: &DwarfVmState) {
-        self.pc = state.pc;
-        self.stack = state.stack.clone();
+    /// The value a comparison pushes for `b`, see `set_bool_repr`
+    fn bool_value(&self, b: bool) -> u64 {
+        match (b, self.bool_repr) {
+            (false, _) => 0,
+            (true, BoolRepr::OneZero) => 1,
+            (true, BoolRepr::AllOnes) => self.generic_mask(),
+        }
+    }
+
+    fn generic_mask(&self) -> u64 {
+        u64::MAX >> (64 - 8 * self.generic_type_size() as u32)
+    }
+
+    /// Sign extend a generic type value to an i64
+    fn sext(&self, v: u64) -> i64 {
+        let shift = 64 - 8 * self.generic_type_size() as u32;
+
+        ((v << shift) as i64) >> shift
     }
 
     fn push(&mut self, v: u64) {
-        self.stack.push(v)
+        let v = v & self.generic_mask();
+
+        self.record(VmEvent::Push(v));
+
+        if let Some(f) = &mut self.stack_observer {
+            f(StackEvent::Push(v));
+        }
+
+        self.stack.push(v);
+
+        if self.stack.len() > self.max_stack_depth {
+            self.max_stack_depth = self.stack.len();
+        }
     }
 
     fn pop(&mut self) -> u64 {
-        self.stack.pop().expect("Attempt to pop from empty stack!")
+        let v = self.stack.pop().expect("Attempt to pop from empty stack!");
+
+        self.record(VmEvent::Pop(v));
+
+        if let Some(f) = &mut self.stack_observer {
+            f(StackEvent::Pop(v));
+        }
+
+        v
     }
 
     fn idx(&self, n: usize) -> u64 {
@@ -350,13 +3317,55 @@ impl<'a> DwarfVm<'a> {
             .expect("Attempt to index past stack bounds")
     }
 
+    /// Format the top `depth` stack entries, one per line, keyed by offset
+    pub fn format_stack(&self, depth: usize) -> String {
+        let mut s = String::new();
+
+        for (ii, vv) in self.stack.iter().rev().take(depth).enumerate() {
+            s.push_str(&format!("{:02x} | {:016x}\n", ii * 8, vv));
+        }
+
+        s
+    }
+
+    /// Dump the VM for bug reports and panic hooks
+    ///
+    /// The terse dump is the `Display` output plus the current op, the verbose
+    /// dump adds the context, overlay, breakpoints, counters and whole stack.
+    pub fn dump(&self, verbose: bool) -> String {
+        let op = match self.peek_mnemonic() {
+            Ok((_, op)) => op,
+            Err(e) => format!("<{}>", e),
+        };
+
+        let mut s = format!("{}\nop: {}\n", self, op);
+
+        if verbose {
+            s.push_str(&format!("ctx: {:#x}\n", self.ctx));
+            s.push_str(&format!("entry: {:#x}\n", self.entry));
+            s.push_str(&format!("insn count: {}\n", self.insn_count));
+            s.push_str(&format!("reads: {}\n", self.reads));
+            s.push_str(&format!("overlay regions: {}\n", self.overlay.len()));
+            s.push_str(&format!(
+                "breakpoints: {:x?}\n",
+                self.breakpoints.keys().collect::<Vec<_>>()
+            ));
+            s.push_str(&format!("stack depth: {}\n", self.stack.len()));
+            s.push_str(&self.format_stack(self.stack.len()));
+        }
+
+        s
+    }
+
     /// Log the current state via warn
+    ///
+    /// An op which fails to decode is logged as the error rather than failing.
     pub fn log_state(&self, stack_amt: usize) -> Result<(), DwarfVmError> {
-        let (_, op) = decode(self.target_read(self.pc)).map_err(|_| DwarfVmError::Decode)?;
+        let op = self.state_mnemonic()?;
         warn!("pc: 0x{:04x} [{}]", self.pc, op);
         warn!("sp: 0x{:04x}", self.stack.len() * 8);
-        for (ii, vv) in self.stack.iter().rev().take(stack_amt).enumerate() {
-            warn!("{:02x} | {:016x}", ii * 8, vv);
+        for line in self.format_stack(stack_amt).lines() {
+            warn!("{}", line);
         }
         warn!("------------");
 
@@ -364,12 +3373,18 @@ impl<'a> DwarfVm<'a> {
     }
 
     /// Log the current state via trace
+    ///
+    /// An op which fails to decode is logged as the error rather than failing.
     pub fn trace_state(&self, stack_amt: usize) -> Result<(), DwarfVmError> {
-        let (_, op) = decode(self.target_read(self.pc)).map_err(|_| DwarfVmError::Decode)?;
+        if !log_enabled!(Level::Trace) {
+            return Ok(());
+        }
+
+        let op = self.state_mnemonic()?;
         trace!("pc: 0x{:04x} [{}]", self.pc, op);
         trace!("sp: 0x{:04x}", self.stack.len() * 8);
-        for (ii, vv) in self.stack.iter().rev().take(stack_amt).enumerate() {
-            trace!("{:02x} | {:016x}", ii * 8, vv);
+        for line in self.format_stack(stack_amt).lines() {
+            trace!("{}", line);
         }
         trace!("------------");
 
@@ -378,11 +3393,123 @@ impl<'a> DwarfVm<'a> {
 
     /// Get the current memory overlay BTree
     pub fn overlay(&mut self) -> &mut BTreeMap<u64, Vec<u8>> {
-        // Note that memory overlay checks are very inefficient right now, if you
-        // need to make heavy use of these please file an issue.
         &mut self.overlay
     }
 
+    /// Read memory from a running process
+    ///
+    /// Pages are read from the process as ops touch them, and cached. They are
+    /// checked after the overlay and `map_borrowed` regions and before the
+    /// core. Registers aren't read from the process, there is no ptrace
+    /// support, so use `set_register_provider`. Only instruction fetches and
+    /// reads made by `step` read new pages.
+    #[cfg(feature = "linux-live")]
+    pub fn set_live_memory(&mut self, mem: ProcMem) {
+        self.live = Some(mem);
+    }
+
+    /// Map borrowed memory at `addr`, e.g. pages from an mmap'd capture
+    ///
+    /// Unlike `overlay`, the bytes aren't copied. Mapped regions are checked
+    /// after the overlay and before the core, and are not part of an
+    /// `overlay_snapshot`.
+    pub fn map_borrowed(&mut self, addr: u64, data: &'a [u8]) {
+        self.mapped.insert(addr, data);
+    }
+
+    /// Copy `bytes` into the overlay at the first `align` aligned address at
+    /// or after `base`, returning that address
+    ///
+    /// An `align` of 0 or 1 places the program at `base`. Returns `None`,
+    /// mapping nothing, if there is no such address before the end of the
+    /// address space. The PC is left alone, see `load_location_expr` to also
+    /// run the program.
+    pub fn map_program_aligned(&mut self, bytes: &[u8], base: u64, align: u64) -> Option<u64> {
+        let base = match align {
+            0 | 1 => base,
+            _ => base.checked_add(align - 1)? / align * align,
+        };
+
+        self.overlay.insert(base, bytes.to_vec());
+
+        Some(base)
+    }
+
+    /// List the bytes where two VMs' views of memory differ
+    ///
+    /// Every byte covered by either VM's overlay is compared through each
+    /// VM's full view of memory, so a region present in only one overlay is
+    /// compared against the other VM's core. Yields `(addr, a_byte, b_byte)`
+    /// in address order, with `None` for a byte unmapped in that view.
+    /// `map_borrowed` regions are only compared where they sit under an
+    /// overlay region.
+    pub fn overlay_diff(a: &DwarfVm, b: &DwarfVm) -> Vec<(u64, Option<u8>, Option<u8>)> {
+        let mut xs = a.overlay.iter().peekable();
+        let mut ys = b.overlay.iter().peekable();
+
+        let mut diffs = Vec::new();
+
+        // first address not yet compared, so overlapping regions are only
+        // walked once
+        let mut next = 0;
+
+        loop {
+            // walk both overlays in lockstep, lowest base first
+            let (&base, v) = match (xs.peek(), ys.peek()) {
+                (Some(x), Some(y)) if y.0 < x.0 => ys.next().unwrap(),
+                (Some(_), _) => xs.next().unwrap(),
+                (None, Some(_)) => ys.next().unwrap(),
+                (None, None) => break,
+            };
+
+            let end = base.saturating_add(v.len() as u64);
+            let mut addr = base.max(next);
+
+            // compare a contiguous run of each view at a time, at least one
+            // side is always the overlay region itself
+            while addr < end {
+                let x = a.try_target_read(addr).filter(|x| !x.is_empty());
+                let y = b.try_target_read(addr).filter(|x| !x.is_empty());
+
+                let mut n = end - addr;
+                for run in x.iter().chain(y.iter()) {
+                    n = n.min(run.len() as u64);
+                }
+                for vm in [a, b].iter() {
+                    if let Some(start) = vm.next_region_start(addr) {
+                        n = n.min(start - addr);
+                    }
+                }
+
+                for ii in 0..n as usize {
+                    let xb = x.and_then(|x| x.get(ii)).copied();
+                    let yb = y.and_then(|y| y.get(ii)).copied();
+
+                    if xb != yb {
+                        diffs.push((addr + ii as u64, xb, yb));
+                    }
+                }
+
+                addr += n;
+            }
+
+            next = next.max(end);
+        }
+
+        diffs
+    }
+
+    /// Iterate the memory overlay regions
+    ///
+    /// Yields the base address and contents of each region, the overlay in
+    /// address order followed by the `map_borrowed` regions in address order.
+    pub fn overlay_regions(&self) -> impl Iterator<Item = (u64, &[u8])> {
+        self.overlay
+            .iter()
+            .map(|(base, v)| (*base, v.as_slice()))
+            .chain(self.mapped.iter().map(|(base, v)| (*base, *v)))
+    }
+
     /// get the current breakpoints BTree
     pub fn breakpoints(
         &mut self,
@@ -391,6 +3518,16 @@ impl<'a> DwarfVm<'a> {
     }
 
     /// Add a new breakpoint
+    ///
+    /// The breakpoint runs before the op at `pc` executes, and may modify the
+    /// op. Returning true stops execution with `DwarfVmError::Breakpoint`
+    /// without consuming the op. The next step then executes the op without
+    /// consulting the breakpoint, so `run` continues past it rather than
+    /// stopping again, while later visits, e.g. in a loop, still stop. If the
+    /// breakpoint changes the PC the op is discarded, and the op at the new
+    /// PC is decoded and executed in its place, consulting any breakpoint
+    /// there. At an `ExtOp`, which can't be represented as an `Op`, the
+    /// breakpoint is passed `Op::Nop` and changes to it are ignored.
     pub fn set_breakpoint<F: 'static + FnMut(&mut Self, &mut Op) -> bool>(
         &mut self,
         pc: u64,
@@ -399,67 +3536,434 @@ impl<'a> DwarfVm<'a> {
         self.breakpoints.insert(pc, Box::new(bkpt));
     }
 
-    fn target_read(&self, a: u64) -> &[u8] {
-        // first check the overlay
-        for (start, v) in &self.overlay {
-            let end = *start + v.len() as u64;
+    /// Install a hook run each time a `DW_OP_nop` executes
+    ///
+    /// The hook runs with the PC after the nop. Returning `Break` stops
+    /// execution with `DwarfVmError::Breakpoint`, the nop has been consumed so
+    /// resuming continues with the next op.
+    pub fn set_nop_hook<F: 'static + FnMut(&mut Self) -> ControlFlow<()>>(&mut self, f: F) {
+        self.nop_hook = Some(Box::new(f));
+    }
+
+    /// Get the name of the core section containing the PC
+    ///
+    /// Returns `None` if the PC is in the overlay, or in no named section.
+    pub fn current_section(&self) -> Option<&str> {
+        if self.overlay_contains(self.pc) {
+            return None;
+        }
+
+        self.find_section(self.core_addr(self.pc))?
+            .get_name(&self.elf()?)
+            .ok()
+    }
 
-            if a >= *start && a < end {
-                let off = (a - *start) as usize;
-                return &v[off..];
+    /// Get the raw contents of the core section `name`
+    ///
+    /// Compressed sections are returned as is. Returns `None` if there is no
+    /// such section, it has no data in the file, e.g. `.bss`, or the VM has no
+    /// core. Sections whose names can't be read are skipped.
+    pub fn section_data(&self, name: &str) -> Option<&[u8]> {
+        self.named_section(name).map(|(_, data)| data)
+    }
+
+    /// Get the flags and raw contents of the core section `name`, see
+    /// `section_data`
+    fn named_section(&self, name: &str) -> Option<(u64, &[u8])> {
+        let core = self.elf()?;
+        let sec = core.find_section_by_name(name)?;
+
+        if matches!(sec.get_type(), Ok(ShType::Null) | Ok(ShType::NoBits)) {
+            return None;
+        }
+
+        let start = sec.offset() as usize;
+        let end = start.checked_add(sec.size() as usize)?;
+
+        Some((sec.flags(), core.input.get(start..end)?))
+    }
+
+    /// Translate a runtime address to the address space of the core
+    fn core_addr(&self, a: u64) -> u64 {
+        a.wrapping_sub(self.load_bias as u64)
+    }
+
+    /// Parse the core, if there is one
+    ///
+    /// Only the ELF header is parsed, and the core was checked when the VM was
+    /// created, so this is cheap and doesn't fail.
+    fn elf(&self) -> Option<ElfFile<'_>> {
+        ElfFile::new(self.core.as_ref()?.bytes()).ok()
+    }
+
+    /// Find the section backing core address `a`
+    ///
+    /// Compressed sections are skipped, they can't be allocated so never hold
+    /// memory contents, and their raw bytes are not what the address holds.
+    fn find_section(&self, a: u64) -> Option<SectionHeader<'_>> {
+        self.elf()?.section_iter().find(|&x| {
+            x.flags() & compress::SHF_COMPRESSED == 0
+                && a >= x.address()
+                && a - x.address() < x.size()
+        })
+    }
+
+    fn overlay_contains(&self, a: u64) -> bool {
+        self.overlay_lookup(a).is_some()
+    }
+
+    /// Find `a` in the overlay then the borrowed mappings
+    fn overlay_lookup(&self, a: u64) -> Option<&[u8]> {
+        region_lookup(&self.overlay, a)
+            .or_else(|| region_lookup(&self.mapped, a))
+            .or_else(|| self.live_lookup(a))
+    }
+
+    #[cfg(feature = "linux-live")]
+    fn live_lookup(&self, a: u64) -> Option<&[u8]> {
+        if self.deterministic {
+            return None;
+        }
+
+        self.live.as_ref()?.lookup(a)
+    }
+
+    #[cfg(not(feature = "linux-live"))]
+    fn live_lookup(&self, _a: u64) -> Option<&[u8]> {
+        None
+    }
+
+    /// Read the live memory covering `a..a + len`, see `set_live_memory`
+    #[cfg(feature = "linux-live")]
+    fn fault_in(&mut self, a: u64, len: u64) {
+        if self.deterministic {
+            return;
+        }
+
+        if let Some(live) = &mut self.live {
+            live.fault_in(a, len);
+        }
+    }
+
+    #[cfg(not(feature = "linux-live"))]
+    fn fault_in(&mut self, _a: u64, _len: u64) {}
+
+    /// Read the live memory covering the op at the PC
+    ///
+    /// Ops may straddle a page, and those with a block operand may be longer
+    /// than `ext::MAX_OP_LEN`, so the block is faulted in once its length is
+    /// readable.
+    #[cfg(feature = "linux-live")]
+    fn fault_in_op(&mut self) {
+        self.fault_in(self.pc, ext::MAX_OP_LEN);
+
+        if let Some(len) = self.try_target_read(self.pc).and_then(ext::block_op_len) {
+            self.fault_in(self.pc, len);
+        }
+    }
+
+    #[cfg(not(feature = "linux-live"))]
+    fn fault_in_op(&mut self) {}
+
+    /// The base of the first overlay or mapped region after `a`
+    ///
+    /// A read at `a` may run into such a region, which takes priority.
+    fn next_region_start(&self, a: u64) -> Option<u64> {
+        let after = a.checked_add(1)?;
+        let x = self.overlay.range(after..).next().map(|(&k, _)| k);
+        let y = self.mapped.range(after..).next().map(|(&k, _)| k);
+
+        x.into_iter().chain(y).min()
+    }
+
+    /// Assert the top of the stack whenever execution reaches `pc`
+    ///
+    /// `expected_top` lists the top entries bottom to top, as in `top_state`.
+    /// On a mismatch the step fails with `DwarfVmError::AssertionFailed`. This
+    /// replaces any breakpoint at `pc`.
+    pub fn assert_at(&mut self, pc: u64, expected_top: &[u64]) {
+        let expected = expected_top.to_vec();
+
+        self.set_breakpoint(pc, move |dvm, _| {
+            let skip = dvm.stack.len().saturating_sub(expected.len());
+            let actual = &dvm.stack[skip..];
+
+            if actual == &expected[..] {
+                return false;
+            }
+
+            dvm.bkpt_error = Some(DwarfVmError::AssertionFailed {
+                pc: dvm.pc,
+                expected: expected.clone(),
+                actual: actual.to_vec(),
+            });
+
+            true
+        });
+    }
+
+    fn charge_gas(&mut self, cost: u64) -> Result<(), DwarfVmError> {
+        let used = self.gas_used.saturating_add(cost);
+
+        match self.gas_limit {
+            Some(limit) if used > limit => Err(DwarfVmError::OutOfGas { pc: self.pc }),
+            _ => {
+                self.gas_used = used;
+                Ok(())
+            }
+        }
+    }
+
+    fn charge_read(&mut self) -> Result<(), DwarfVmError> {
+        self.reads += 1;
+
+        match self.read_budget {
+            Some(budget) if self.reads > budget => {
+                Err(DwarfVmError::ReadBudgetExceeded { pc: self.pc })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Is all of `a..a + len` backed by the overlay or core
+    fn is_mapped(&self, a: u64, len: u64) -> bool {
+        self.unmapped_in(a, len).is_none()
+    }
+
+    /// Find the first address in `a..a + len` not backed by the overlay or core
+    fn unmapped_in(&self, a: u64, len: u64) -> Option<u64> {
+        let end = a.saturating_add(len);
+        let mut cur = a;
+
+        while cur < end {
+            match self.try_target_read(cur) {
+                Some(data) if !data.is_empty() => cur = cur.saturating_add(data.len() as u64),
+                _ => return Some(cur),
             }
         }
 
+        None
+    }
+
+    fn try_target_read(&self, a: u64) -> Option<&[u8]> {
+        // first check the overlay
+        if let Some(data) = self.overlay_lookup(a) {
+            return Some(data);
+        }
+
         // then check the core
-        let sec = self
-            .core
-            .section_iter()
-            .find(|&x| a >= x.address() && a <= x.address() + x.size())
-            .expect(&format!("Could not find section for address {:#x}", a));
+        self.core_read(self.core_addr(a)).map(|(data, _)| data)
+    }
+
+    /// Read from the core at core address `a`, along with the section hit
+    ///
+    /// Returns no section if the read was satisfied by a segment.
+    fn core_read(&self, a: u64) -> Option<(&[u8], Option<SectionHeader<'_>>)> {
+        let core = self.elf()?;
+
+        if let Some(sec) = self.find_section(a) {
+            let data = sec.raw_data(&core);
+            let off = (a - sec.address()) as usize;
+
+            return Some((&data[off..], Some(sec)));
+        }
+
+        // fall back to the segments, some cores have no section table
+        let ph = core.program_iter().find(|x| {
+            matches!(x.get_type(), Ok(program::Type::Load))
+                && a >= x.virtual_addr()
+                && a < x.virtual_addr() + x.file_size()
+        })?;
+
+        let start = ph.offset() + (a - ph.virtual_addr());
+        let end = ph.offset() + ph.file_size();
+
+        Some((core.input.get(start as usize..end as usize)?, None))
+    }
+
+    /// Read `len` bytes at `addr`, reporting where they came from
+    ///
+    /// Memory is resolved exactly as for an op's read, including the short
+    /// read policy and the unmapped fill, but the read isn't charged against
+    /// the read budget or recorded.
+    pub fn read_with_source(
+        &self,
+        addr: u64,
+        len: usize,
+    ) -> Result<(Vec<u8>, ReadSource), DwarfVmError> {
+        let found = if let Some(data) = region_lookup(&self.overlay, addr) {
+            Some((data, ReadSource::Overlay))
+        } else if let Some(data) = region_lookup(&self.mapped, addr) {
+            Some((data, ReadSource::Mapped))
+        } else if let Some(data) = self.live_lookup(addr) {
+            Some((data, ReadSource::Live))
+        } else {
+            self.core_read(self.core_addr(addr)).map(|(data, sec)| {
+                let source = match sec {
+                    Some(sec) => {
+                        let name = self.elf().and_then(|x| sec.get_name(&x).ok());
+
+                        ReadSource::Section(name.unwrap_or("").to_string())
+                    }
+                    None => ReadSource::Segment,
+                };
+
+                (data, source)
+            })
+        };
+
+        if let Some((data, source)) = found {
+            if let Some(data) = data.get(..len) {
+                return Ok((data.to_vec(), source));
+            }
+
+            if self.short_read == ShortReadPolicy::ZeroExtend && !data.is_empty() {
+                let mut data = data.to_vec();
+                data.resize(len, 0);
+
+                return Ok((data, source));
+            }
+        }
+
+        let fill = self.poison().ok_or(DwarfVmError::Unmapped { addr })?;
+
+        let data = fill
+            .to_le_bytes()
+            .iter()
+            .copied()
+            .cycle()
+            .take(len)
+            .collect();
+
+        Ok((data, ReadSource::Poison))
+    }
+
+    /// Read `sz` bytes for a typed read, zero padded to 8 bytes
+    ///
+    /// Reads which are not wholly mapped are satisfied from the unmapped fill
+    /// if one is set, in which case the read is flagged as synthetic.
+    fn target_read_exact(&mut self, a: u64, sz: usize) -> Result<([u8; 8], bool), DwarfVmError> {
+        let mut buf = [0u8; 8];
 
-        let data = sec.raw_data(&self.core);
-        let off = (a - sec.address()) as usize;
+        self.fault_in(a, sz as u64);
 
-        &data[off..]
+        if self.boundary_read_hook.is_some() {
+            self.check_boundary_read(a, sz);
+        }
+
+        if let Some(data) = self.try_target_read(a) {
+            if let Some(data) = data.get(..sz) {
+                buf[..sz].copy_from_slice(data);
+
+                return Ok((buf, false));
+            }
+
+            if self.short_read == ShortReadPolicy::ZeroExtend && !data.is_empty() {
+                debug!("short read 0x{:016x}, {} of {} bytes", a, data.len(), sz);
+
+                buf[..data.len()].copy_from_slice(data);
+
+                return Ok((buf, true));
+            }
+        }
+
+        let fill = self.poison().ok_or(DwarfVmError::Unmapped { addr: a })?;
+
+        debug!("synthetic read 0x{:016x}", a);
+
+        buf[..sz].copy_from_slice(&fill.to_le_bytes()[..sz]);
+
+        Ok((buf, true))
+    }
+
+    /// Call the boundary read hook if `a..a + sz` starts in the overlay and
+    /// ends in the core, see `set_boundary_read_hook`
+    fn check_boundary_read(&mut self, a: u64, sz: usize) {
+        let len = match self.overlay_lookup(a) {
+            Some(data) if data.len() < sz => data.len(),
+            _ => return,
+        };
+
+        let next = self.core_addr(a + len as u64);
+        if self.core_read(next).is_none() {
+            return;
+        }
+
+        if let Some(mut hook) = self.boundary_read_hook.take() {
+            hook(a, len, sz);
+            self.boundary_read_hook = Some(hook);
+        }
     }
 
-    fn target_read_u8(&self, a: u64) -> u8 {
-        let data = self.target_read(a);
+    fn target_read_u8(&mut self, a: u64) -> Result<u8, DwarfVmError> {
+        self.charge_read()?;
+
+        let (data, synthetic) = self.target_read_exact(a, 1)?;
 
         let v = data[0];
 
         trace!("read u8  0x{:016x} = 0x{:02x}", a, v);
-
-        v
+        self.record(VmEvent::Read(MemRead {
+            addr: a,
+            size: 1,
+            value: v as u64,
+            synthetic,
+        }));
+
+        Ok(v)
     }
 
-    fn target_read_u16(&self, a: u64) -> u16 {
-        let data = self.target_read(a);
+    fn target_read_u16(&mut self, a: u64) -> Result<u16, DwarfVmError> {
+        self.charge_read()?;
+
+        let (data, synthetic) = self.target_read_exact(a, 2)?;
 
         let v = u16::from_le_bytes(data[..2].try_into().unwrap());
 
         trace!("read u16 0x{:016x} = 0x{:04x}", a, v);
-
-        v
+        self.record(VmEvent::Read(MemRead {
+            addr: a,
+            size: 2,
+            value: v as u64,
+            synthetic,
+        }));
+
+        Ok(v)
     }
 
-    fn target_read_u32(&self, a: u64) -> u32 {
-        let data = self.target_read(a);
+    fn target_read_u32(&mut self, a: u64) -> Result<u32, DwarfVmError> {
+        self.charge_read()?;
+
+        let (data, synthetic) = self.target_read_exact(a, 4)?;
 
         let v = u32::from_le_bytes(data[..4].try_into().unwrap());
 
         trace!("read u32 0x{:016x} = 0x{:08x}", a, v);
-
-        v
+        self.record(VmEvent::Read(MemRead {
+            addr: a,
+            size: 4,
+            value: v as u64,
+            synthetic,
+        }));
+
+        Ok(v)
     }
 
-    fn target_read_u64(&self, a: u64) -> u64 {
-        let data = self.target_read(a);
+    fn target_read_u64(&mut self, a: u64) -> Result<u64, DwarfVmError> {
+        self.charge_read()?;
+
+        let (data, synthetic) = self.target_read_exact(a, 8)?;
 
         let v = u64::from_le_bytes(data[..8].try_into().unwrap());
 
         trace!("read u64 0x{:016x} = 0x{:016x}", a, v);
-
-        v
+        self.record(VmEvent::Read(MemRead {
+            addr: a,
+            size: 8,
+            value: v as u64,
+            synthetic,
+        }));
+
+        Ok(v)
     }
 }
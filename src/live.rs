@@ -0,0 +1,119 @@
+//! Reading the memory of a running process, see `DwarfVm::set_live_memory`
+//!
+//! Only memory is read live. Registers are not seeded from the process with
+//! ptrace, supply them with `DwarfVm::set_register_provider`. `ProcMem` is the
+//! only live backend, there is no trait for plugging in other memory sources.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io;
+use std::os::unix::fs::FileExt;
+
+use crate::region_lookup;
+
+const PAGE_SIZE: u64 = 0x1000;
+
+/// The memory of a running process, read through `/proc/<pid>/mem`
+///
+/// Pages are read the first time they are touched and cached, adjacent pages
+/// are merged so reads and ops can span page boundaries.
+pub struct ProcMem {
+    file: File,
+    runs: BTreeMap<u64, Vec<u8>>,
+    unmapped: BTreeSet<u64>,
+}
+
+impl ProcMem {
+    /// Open the memory of process `pid`, this needs ptrace access to it
+    pub fn open(pid: u32) -> io::Result<Self> {
+        let file = File::open(format!("/proc/{}/mem", pid))?;
+
+        Ok(Self {
+            file,
+            runs: BTreeMap::new(),
+            unmapped: BTreeSet::new(),
+        })
+    }
+
+    /// Read every page covering `addr..addr + len` into the cache
+    ///
+    /// Stops at the first unmapped page, as no read can span it.
+    pub(crate) fn fault_in(&mut self, addr: u64, len: u64) {
+        let start = addr & !(PAGE_SIZE - 1);
+        let end = addr.saturating_add(len);
+
+        for page in (start..end).step_by(PAGE_SIZE as usize) {
+            if !self.load(page) {
+                break;
+            }
+        }
+    }
+
+    /// Find `a` in the pages read so far
+    pub(crate) fn lookup(&self, a: u64) -> Option<&[u8]> {
+        region_lookup(&self.runs, a)
+    }
+
+    /// Read `page` into the cache, returning whether it is mapped
+    fn load(&mut self, page: u64) -> bool {
+        if self.unmapped.contains(&page) {
+            return false;
+        }
+
+        if self.lookup(page).is_some() {
+            return true;
+        }
+
+        let data = match read_page(&self.file, page) {
+            Some(data) => data,
+            None => {
+                self.unmapped.insert(page);
+                return false;
+            }
+        };
+
+        // append to the run ending at this page, if any
+        let prev = self
+            .runs
+            .range(..page)
+            .next_back()
+            .filter(|(base, v)| **base + v.len() as u64 == page)
+            .map(|(base, _)| *base);
+
+        let base = match prev {
+            Some(base) => {
+                self.runs.get_mut(&base).unwrap().extend_from_slice(&data);
+                base
+            }
+            None => {
+                self.runs.insert(page, data);
+                page
+            }
+        };
+
+        // then pull in the run starting right after it
+        if let Some(next) = self.runs.remove(&(page + PAGE_SIZE)) {
+            self.runs.get_mut(&base).unwrap().extend_from_slice(&next);
+        }
+
+        true
+    }
+}
+
+/// Read a whole page, `None` if any of it is unmapped
+fn read_page(file: &File, page: u64) -> Option<Vec<u8>> {
+    let mut buf = vec![0; PAGE_SIZE as usize];
+    let mut done = 0;
+
+    while done < buf.len() {
+        match file.read_at(&mut buf[done..], page + done as u64) {
+            Ok(0) => return None,
+            Ok(n) => done += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            // the kernel returns EIO for unmapped pages
+            Err(_) => return None,
+        }
+    }
+
+    Some(buf)
+}
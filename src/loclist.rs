@@ -0,0 +1,158 @@
+//! Location list parsing for `.debug_loc` and `.debug_loclists`
+
+use std::convert::TryInto;
+
+use crate::DwarfVmError;
+
+const DW_LLE_END_OF_LIST: u8 = 0x00;
+const DW_LLE_OFFSET_PAIR: u8 = 0x04;
+const DW_LLE_DEFAULT_LOCATION: u8 = 0x05;
+const DW_LLE_BASE_ADDRESS: u8 = 0x06;
+const DW_LLE_START_END: u8 = 0x07;
+const DW_LLE_START_LENGTH: u8 = 0x08;
+
+fn read_bytes<'a>(data: &'a [u8], off: &mut usize, sz: usize) -> Result<&'a [u8], DwarfVmError> {
+    let end = off.checked_add(sz).ok_or(DwarfVmError::BadLocList)?;
+    let v = data.get(*off..end).ok_or(DwarfVmError::BadLocList)?;
+
+    *off = end;
+
+    Ok(v)
+}
+
+fn read_u8(data: &[u8], off: &mut usize) -> Result<u8, DwarfVmError> {
+    Ok(read_bytes(data, off, 1)?[0])
+}
+
+fn read_u16(data: &[u8], off: &mut usize) -> Result<u16, DwarfVmError> {
+    let v = read_bytes(data, off, 2)?;
+
+    Ok(u16::from_le_bytes(v.try_into().unwrap()))
+}
+
+fn read_addr(data: &[u8], off: &mut usize, addr_size: u8) -> Result<u64, DwarfVmError> {
+    let v = read_bytes(data, off, addr_size as usize)?;
+
+    let mut buf = [0u8; 8];
+    buf[..v.len()].copy_from_slice(v);
+
+    Ok(u64::from_le_bytes(buf))
+}
+
+fn read_uleb(data: &[u8], off: &mut usize) -> Result<u64, DwarfVmError> {
+    let mut v = 0;
+    let mut shift = 0;
+
+    loop {
+        let b = read_u8(data, off)?;
+
+        if shift < 64 {
+            v |= ((b & 0x7f) as u64) << shift;
+        }
+        shift += 7;
+
+        if b & 0x80 == 0 {
+            return Ok(v);
+        }
+    }
+}
+
+/// Find the expression covering `pc` in a DWARF 2-4 `.debug_loc` list
+///
+/// Entries are relative to `cu_base`, the CU's `DW_AT_low_pc`, until a base
+/// address selection entry replaces it.
+pub(crate) fn find_loc(
+    data: &[u8],
+    offset: usize,
+    pc: u64,
+    cu_base: u64,
+    addr_size: u8,
+) -> Result<&[u8], DwarfVmError> {
+    let base_sel = if addr_size == 4 {
+        u32::MAX as u64
+    } else {
+        u64::MAX
+    };
+
+    let mut off = offset;
+    let mut base = cu_base;
+
+    loop {
+        let begin = read_addr(data, &mut off, addr_size)?;
+        let end = read_addr(data, &mut off, addr_size)?;
+
+        if begin == 0 && end == 0 {
+            return Err(DwarfVmError::NoLocation { target_pc: pc });
+        }
+
+        if begin == base_sel {
+            base = end;
+            continue;
+        }
+
+        let len = read_u16(data, &mut off)? as usize;
+        let expr = read_bytes(data, &mut off, len)?;
+
+        if pc >= base.wrapping_add(begin) && pc < base.wrapping_add(end) {
+            return Ok(expr);
+        }
+    }
+}
+
+/// Find the expression covering `pc` in a DWARF 5 `.debug_loclists` list
+///
+/// Offset pairs are relative to `cu_base`, the CU's `DW_AT_low_pc`, until a
+/// `DW_LLE_base_address` entry replaces it. The indexed (`x`) entry kinds
+/// need `.debug_addr` and are not supported.
+pub(crate) fn find_loclists(
+    data: &[u8],
+    offset: usize,
+    pc: u64,
+    cu_base: u64,
+    addr_size: u8,
+) -> Result<&[u8], DwarfVmError> {
+    let mut off = offset;
+    let mut base = cu_base;
+    let mut default = None;
+
+    loop {
+        let (begin, end) = match read_u8(data, &mut off)? {
+            DW_LLE_END_OF_LIST => return default.ok_or(DwarfVmError::NoLocation { target_pc: pc }),
+            DW_LLE_BASE_ADDRESS => {
+                base = read_addr(data, &mut off, addr_size)?;
+                continue;
+            }
+            DW_LLE_DEFAULT_LOCATION => {
+                let len = read_uleb(data, &mut off)? as usize;
+                default = Some(read_bytes(data, &mut off, len)?);
+                continue;
+            }
+            DW_LLE_OFFSET_PAIR => {
+                let begin = read_uleb(data, &mut off)?;
+                let end = read_uleb(data, &mut off)?;
+
+                (base.wrapping_add(begin), base.wrapping_add(end))
+            }
+            DW_LLE_START_END => {
+                let begin = read_addr(data, &mut off, addr_size)?;
+                let end = read_addr(data, &mut off, addr_size)?;
+
+                (begin, end)
+            }
+            DW_LLE_START_LENGTH => {
+                let begin = read_addr(data, &mut off, addr_size)?;
+                let len = read_uleb(data, &mut off)?;
+
+                (begin, begin.wrapping_add(len))
+            }
+            _ => return Err(DwarfVmError::BadLocList),
+        };
+
+        let len = read_uleb(data, &mut off)? as usize;
+        let expr = read_bytes(data, &mut off, len)?;
+
+        if pc >= begin && pc < end {
+            return Ok(expr);
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! ELF core note parsing
+
+use std::convert::TryInto;
+
+use xmas_elf::program::Type;
+use xmas_elf::ElfFile;
+
+const NT_PRSTATUS: u32 = 1;
+const NT_ARM_TLS: u32 = 0x401;
+
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+
+// offset of pr_reg in the 64-bit elf_prstatus
+const PR_REG_OFF: usize = 112;
+
+// x86_64 user_regs_struct index of fs_base
+const X86_64_FS_BASE: usize = 21;
+
+// x86_64 user_regs_struct index for each DWARF register number
+const X86_64_DWARF_REGS: [usize; 17] = [10, 12, 11, 5, 13, 14, 4, 19, 9, 8, 7, 6, 3, 2, 1, 0, 16];
+
+/// The general purpose registers of a single thread from an `NT_PRSTATUS` note
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub struct ThreadRegs {
+    pub pid: u32,
+    machine: u16,
+    regs: Vec<u64>,
+    tpidr: Option<u64>,
+}
+
+impl ThreadRegs {
+    /// The raw register set, in the order of the kernel's `pr_reg`
+    pub fn regs(&self) -> &[u64] {
+        &self.regs
+    }
+
+    /// Get a register by its DWARF register number
+    ///
+    /// Only x86_64 and aarch64 register numbering is known.
+    pub fn dwarf_reg(&self, r: u64) -> Option<u64> {
+        let idx = match self.machine {
+            EM_AARCH64 if r <= 31 => r as usize,
+            EM_X86_64 => *X86_64_DWARF_REGS.get(r as usize)?,
+            _ => return None,
+        };
+
+        self.regs.get(idx).copied()
+    }
+
+    /// Get the thread pointer, `fs_base` on x86_64 and `tpidr_el0` on aarch64
+    ///
+    /// On aarch64 this comes from the `NT_ARM_TLS` note, which not all cores
+    /// include.
+    pub fn thread_pointer(&self) -> Option<u64> {
+        match self.machine {
+            EM_AARCH64 => self.tpidr,
+            EM_X86_64 => self.regs.get(X86_64_FS_BASE).copied(),
+            _ => None,
+        }
+    }
+}
+
+fn read_u32(data: &[u8], off: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        data.get(off..off + 4)?.try_into().unwrap(),
+    ))
+}
+
+fn align4(v: usize) -> usize {
+    (v + 3) & !3
+}
+
+/// Iterate `(type, name, desc)` for every note in a PT_NOTE segment
+pub(crate) fn notes(data: &[u8]) -> impl Iterator<Item = (u32, &[u8], &[u8])> {
+    let mut off = 0;
+
+    std::iter::from_fn(move || {
+        let namesz = read_u32(data, off)? as usize;
+        let descsz = read_u32(data, off + 4)? as usize;
+        let ty = read_u32(data, off + 8)?;
+
+        let name_off = off + 12;
+        let desc_off = name_off + align4(namesz);
+
+        let name = data.get(name_off..name_off + namesz)?;
+        let desc = data.get(desc_off..desc_off + descsz)?;
+
+        off = desc_off + align4(descsz);
+
+        Some((ty, name, desc))
+    })
+}
+
+/// Iterate the contents of every PT_NOTE segment in the core
+pub(crate) fn note_segments<'a, 'b>(core: &'b ElfFile<'a>) -> impl Iterator<Item = &'a [u8]> + 'b {
+    core.program_iter()
+        .filter(|ph| matches!(ph.get_type(), Ok(Type::Note)))
+        .filter_map(move |ph| {
+            let start = ph.offset() as usize;
+            let end = start.checked_add(ph.file_size() as usize)?;
+
+            core.input.get(start..end)
+        })
+}
+
+fn parse_prstatus(machine: u16, desc: &[u8]) -> Option<ThreadRegs> {
+    let pid = read_u32(desc, 32)?;
+
+    // pr_reg is followed by the int pr_fpvalid and padding
+    let regs = desc
+        .get(PR_REG_OFF..desc.len().checked_sub(8)?)?
+        .chunks_exact(8)
+        .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+        .collect();
+
+    Some(ThreadRegs {
+        pid,
+        machine,
+        regs,
+        tpidr: None,
+    })
+}
+
+/// Parse the register set of every thread in a 64-bit core
+pub(crate) fn threads(core: &ElfFile<'_>) -> Vec<ThreadRegs> {
+    let machine = u16::from_le_bytes(core.input[0x12..0x14].try_into().unwrap());
+
+    let mut threads: Vec<ThreadRegs> = Vec::new();
+
+    // each thread's notes follow its NT_PRSTATUS
+    for (ty, name, desc) in note_segments(core).flat_map(notes) {
+        match ty {
+            NT_PRSTATUS if name.starts_with(b"CORE") => {
+                if let Some(thread) = parse_prstatus(machine, desc) {
+                    threads.push(thread);
+                }
+            }
+            NT_ARM_TLS if name.starts_with(b"LINUX") => {
+                if let (Some(thread), Some(v)) = (threads.last_mut(), desc.get(..8)) {
+                    thread.tpidr = Some(u64::from_le_bytes(v.try_into().unwrap()));
+                }
+            }
+            _ => (),
+        }
+    }
+
+    threads
+}
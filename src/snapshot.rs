@@ -0,0 +1,115 @@
+//! The binary format used by `DwarfVm::save` and `DwarfVm::load`
+//!
+//! All integers are little endian. After the magic and version come the PC,
+//! entry, context, instruction count and address size, then the stack as a
+//! count and values, then the overlay as a count and `(addr, len, bytes)`
+//! regions.
+
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+
+use crate::DwarfVmError;
+
+const MAGIC: &[u8; 4] = b"IRIS";
+const VERSION: u8 = 1;
+
+/// The persisted parts of a VM, also kept in memory by `VmCheckpoint`
+#[derive(Clone, Debug, Hash, Eq, PartialEq)]
+pub(crate) struct Snapshot {
+    pub pc: u64,
+    pub entry: u64,
+    pub ctx: u64,
+    pub insn_count: u64,
+    pub addr_size: u8,
+    pub stack: Vec<u64>,
+    pub overlay: BTreeMap<u64, Vec<u8>>,
+}
+
+fn write_u64<W: Write>(w: &mut W, v: u64) -> io::Result<()> {
+    w.write_all(&v.to_le_bytes())
+}
+
+pub(crate) fn write<W: Write>(w: &mut W, snap: &Snapshot) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&[VERSION])?;
+
+    write_u64(w, snap.pc)?;
+    write_u64(w, snap.entry)?;
+    write_u64(w, snap.ctx)?;
+    write_u64(w, snap.insn_count)?;
+    w.write_all(&[snap.addr_size])?;
+
+    write_u64(w, snap.stack.len() as u64)?;
+    for &v in &snap.stack {
+        write_u64(w, v)?;
+    }
+
+    write_u64(w, snap.overlay.len() as u64)?;
+    for (&addr, data) in &snap.overlay {
+        write_u64(w, addr)?;
+        write_u64(w, data.len() as u64)?;
+        w.write_all(data)?;
+    }
+
+    Ok(())
+}
+
+fn read_bytes<R: Read>(r: &mut R, sz: usize) -> Result<Vec<u8>, DwarfVmError> {
+    let mut v = Vec::new();
+    r.take(sz as u64)
+        .read_to_end(&mut v)
+        .map_err(|_| DwarfVmError::BadSnapshot)?;
+
+    if v.len() != sz {
+        return Err(DwarfVmError::BadSnapshot);
+    }
+
+    Ok(v)
+}
+
+fn read_u8<R: Read>(r: &mut R) -> Result<u8, DwarfVmError> {
+    Ok(read_bytes(r, 1)?[0])
+}
+
+fn read_u64<R: Read>(r: &mut R) -> Result<u64, DwarfVmError> {
+    let v = read_bytes(r, 8)?;
+
+    Ok(u64::from_le_bytes(v[..].try_into().unwrap()))
+}
+
+pub(crate) fn read<R: Read>(r: &mut R) -> Result<Snapshot, DwarfVmError> {
+    if read_bytes(r, MAGIC.len())? != MAGIC || read_u8(r)? != VERSION {
+        return Err(DwarfVmError::BadSnapshot);
+    }
+
+    let pc = read_u64(r)?;
+    let entry = read_u64(r)?;
+    let ctx = read_u64(r)?;
+    let insn_count = read_u64(r)?;
+    let addr_size = read_u8(r)?;
+
+    // counts come from the file, so don't trust them for preallocation
+    let mut stack = Vec::new();
+    for _ in 0..read_u64(r)? {
+        stack.push(read_u64(r)?);
+    }
+
+    let mut overlay = BTreeMap::new();
+    for _ in 0..read_u64(r)? {
+        let addr = read_u64(r)?;
+        let len = read_u64(r)?;
+
+        overlay.insert(addr, read_bytes(r, len as usize)?);
+    }
+
+    Ok(Snapshot {
+        pc,
+        entry,
+        ctx,
+        insn_count,
+        addr_size,
+        stack,
+        overlay,
+    })
+}
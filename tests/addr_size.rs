@@ -0,0 +1,189 @@
+use iris::{asm, DwarfVm, DwarfVmError};
+
+#[test]
+fn deref_32() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_addr_size(4);
+
+    // DW_OP_const4u 0x2000; DW_OP_deref
+    dvm.overlay()
+        .insert(0x1000, vec![0x0c, 0x00, 0x20, 0x00, 0x00, 0x06]);
+    dvm.overlay().insert(0x2000, vec![0xff; 8]);
+
+    dvm.step().unwrap();
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.stack, vec![0xffff_ffff]);
+    assert_eq!(dvm.stack[0] >> 32, 0);
+}
+
+#[test]
+fn plus_wraps_32() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_addr_size(4);
+
+    // DW_OP_const4u 0xffffffff; DW_OP_lit1; DW_OP_plus
+    dvm.overlay()
+        .insert(0x1000, vec![0x0c, 0xff, 0xff, 0xff, 0xff, 0x31, 0x22]);
+
+    dvm.run(Some(3)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0]);
+}
+
+#[test]
+fn plus_wraps_generic_1() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_generic_type_size(1);
+
+    // DW_OP_const1u 0xff; DW_OP_lit2; DW_OP_plus
+    dvm.overlay().insert(0x1000, vec![0x08, 0xff, 0x32, 0x22]);
+    dvm.run(Some(3)).unwrap();
+
+    assert_eq!(dvm.stack, vec![1]);
+}
+
+#[test]
+fn plus_wraps_generic_2() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_generic_type_size(2);
+
+    // DW_OP_const2u 0xffff; DW_OP_lit2; DW_OP_plus; DW_OP_lit1; DW_OP_neg
+    dvm.overlay()
+        .insert(0x1000, vec![0x0a, 0xff, 0xff, 0x32, 0x22, 0x31, 0x1f]);
+    dvm.run(Some(5)).unwrap();
+
+    assert_eq!(dvm.stack, vec![1, 0xffff]);
+}
+
+#[test]
+fn overflow_trap() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_overflow_trap(true);
+
+    // DW_OP_const8u 0xffff_ffff_ffff_fff0; DW_OP_plus_uconst 0x0f;
+    // DW_OP_plus_uconst 0x01
+    let mut prog = vec![0x0e];
+    prog.extend_from_slice(&0xffff_ffff_ffff_fff0u64.to_le_bytes());
+    prog.extend_from_slice(&[0x23, 0x0f, 0x23, 0x01]);
+
+    dvm.overlay().insert(0x1000, prog);
+
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.stack, vec![u64::MAX]);
+
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::ArithmeticOverflow { pc: 0x100b })
+    );
+}
+
+#[test]
+fn overflow_trap_32() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_addr_size(4);
+    dvm.set_overflow_trap(true);
+
+    // DW_OP_const4u 0xffff_fff0; DW_OP_lit16; DW_OP_plus
+    dvm.overlay()
+        .insert(0x1000, vec![0x0c, 0xf0, 0xff, 0xff, 0xff, 0x40, 0x22]);
+
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::ArithmeticOverflow { pc: 0x1006 })
+    );
+}
+
+#[test]
+fn overflow_trap_signed() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_overflow_trap(true);
+
+    // -8 + 16 wraps as unsigned, but not as signed
+    let prog = [asm::const8s(-8), asm::plus_uconst(16)];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.run(Some(1)).unwrap();
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::ArithmeticOverflow { pc: 0x1009 })
+    );
+
+    dvm.reset();
+    dvm.set_signed_overflow(true);
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.stack, vec![8]);
+
+    // i64::MAX + 1 fits as unsigned, but not as signed
+    let prog = [asm::const8u(i64::MAX as u64), asm::lit(1), asm::plus()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.reset();
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::ArithmeticOverflow { pc: 0x100a })
+    );
+
+    dvm.reset();
+    dvm.set_signed_overflow(false);
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![1 << 63]);
+}
+
+#[test]
+fn shra_sign_extends() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::const1s(-16), asm::lit(2), asm::shra()];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![-4i64 as u64]);
+
+    // the sign bit of a 2 byte generic type
+    let prog = [asm::const2u(0x8000), asm::lit(4), asm::shra()];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.reset();
+    dvm.set_generic_type_size(2);
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![0xf800]);
+
+    // logical shifts are unchanged
+    let prog = [asm::const2u(0x8000), asm::lit(4), asm::shr()];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.reset();
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![0x0800]);
+}
+
+#[test]
+fn abs_neg_min() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // the most negative value has no positive counterpart, so both wrap
+    let min = i64::MIN as u64;
+
+    let prog = [asm::const8u(min), asm::abs(), asm::const8u(min), asm::neg()];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(4)).unwrap();
+
+    assert_eq!(dvm.stack, vec![min, min]);
+}
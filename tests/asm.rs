@@ -0,0 +1,108 @@
+use iris::{asm, BoolRepr, DwarfVm};
+
+fn eval(prog: &[Vec<u8>]) -> Vec<u64> {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(prog.len())).unwrap();
+
+    dvm.stack
+}
+
+#[test]
+fn leb() {
+    assert_eq!(asm::uleb(624485), vec![0xe5, 0x8e, 0x26]);
+    assert_eq!(asm::sleb(-123456), vec![0xc0, 0xbb, 0x78]);
+    assert_eq!(asm::sleb(63), vec![0x3f]);
+    assert_eq!(asm::sleb(64), vec![0xc0, 0x00]);
+}
+
+#[test]
+fn arith() {
+    assert_eq!(
+        eval(&[asm::const1u(5), asm::const1u(3), asm::plus()]),
+        vec![8]
+    );
+    assert_eq!(
+        eval(&[asm::const1u(5), asm::const1u(3), asm::minus()]),
+        vec![2]
+    );
+    assert_eq!(
+        eval(&[asm::const1u(5), asm::const1u(3), asm::mul()]),
+        vec![15]
+    );
+    assert_eq!(
+        eval(&[asm::const1u(5), asm::const1u(3), asm::div()]),
+        vec![1]
+    );
+    assert_eq!(
+        eval(&[asm::const1u(5), asm::const1u(3), asm::modulo()]),
+        vec![2]
+    );
+    assert_eq!(eval(&[asm::constu(300), asm::plus_uconst(200)]), vec![500]);
+    assert_eq!(eval(&[asm::consts(-5), asm::abs()]), vec![5]);
+    assert_eq!(eval(&[asm::lit(5), asm::neg()]), vec![-5i64 as u64]);
+}
+
+#[test]
+fn stack() {
+    assert_eq!(eval(&[asm::lit(1), asm::dup()]), vec![1, 1]);
+    assert_eq!(eval(&[asm::lit(1), asm::lit(2), asm::drop()]), vec![1]);
+    assert_eq!(
+        eval(&[asm::lit(1), asm::lit(2), asm::over()]),
+        vec![1, 2, 1]
+    );
+    assert_eq!(
+        eval(&[asm::lit(1), asm::lit(2), asm::pick(1)]),
+        vec![1, 2, 1]
+    );
+    assert_eq!(eval(&[asm::lit(1), asm::lit(2), asm::swap()]), vec![2, 1]);
+    assert_eq!(
+        eval(&[asm::lit(1), asm::lit(2), asm::lit(3), asm::rot()]),
+        vec![3, 1, 2]
+    );
+}
+
+#[test]
+fn compare() {
+    assert_eq!(eval(&[asm::lit(1), asm::lit(2), asm::lt()]), vec![1]);
+    assert_eq!(eval(&[asm::lit(1), asm::lit(2), asm::gt()]), vec![0]);
+    assert_eq!(eval(&[asm::lit(2), asm::lit(2), asm::eq()]), vec![1]);
+    assert_eq!(eval(&[asm::lit(2), asm::lit(2), asm::ne()]), vec![0]);
+}
+
+#[test]
+fn branch() {
+    assert_eq!(
+        eval(&[asm::lit(1), asm::bra(1), asm::nop(), asm::lit(7)]),
+        vec![7]
+    );
+    assert_eq!(eval(&[asm::skip(1), asm::nop(), asm::lit(7)]), vec![7]);
+}
+
+#[test]
+fn bool_repr() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_bool_repr(BoolRepr::AllOnes);
+
+    let prog = [
+        asm::lit(1),
+        asm::lit(2),
+        asm::lt(),
+        asm::lit(1),
+        asm::lit(2),
+        asm::gt(),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(6)).unwrap();
+
+    assert_eq!(dvm.stack, vec![u64::MAX, 0]);
+
+    dvm.reset();
+    dvm.set_addr_size(4);
+    dvm.run(Some(3)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0xffff_ffff]);
+}
@@ -0,0 +1,18 @@
+use iris::DwarfVm;
+
+#[test]
+fn batch_eval() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_lit2; DW_OP_plus
+    dvm.overlay().insert(0x1000, vec![0x31, 0x32, 0x22]);
+    // DW_OP_lit4; DW_OP_dup; DW_OP_mul
+    dvm.overlay().insert(0x2000, vec![0x34, 0x12, 0x1e]);
+
+    let results = dvm.batch_eval(&[(0x1000, 0, 3), (0x2000, 0, 3), (0x1000, 0, 0)]);
+
+    assert_eq!(results[0], Ok(3));
+    assert_eq!(results[1], Ok(16));
+    assert!(results[2].is_err());
+}
@@ -0,0 +1,145 @@
+use std::ops::ControlFlow;
+
+use iris::{asm, DwarfVm, DwarfVmError, ExprResult};
+
+#[test]
+fn resume() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_lit2; DW_OP_plus
+    dvm.overlay().insert(0x1000, vec![0x31, 0x32, 0x22]);
+    dvm.set_breakpoint(0x1001, |_, _| true);
+
+    assert_eq!(dvm.run(Some(10)), Ok(1));
+    assert_eq!(dvm.pc, 0x1001);
+    assert_eq!(dvm.insn_count(), 1);
+
+    assert_eq!(dvm.run(Some(2)), Ok(2));
+    assert_eq!(dvm.insn_count(), 3);
+    assert_eq!(dvm.stack, vec![3]);
+}
+
+#[test]
+fn resume_loop() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // count down from 3, stopping at the top of each iteration
+    let prog = [
+        asm::lit(3),
+        asm::lit(1),
+        asm::minus(),
+        asm::dup(),
+        asm::bra(-6),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.set_breakpoint(0x1001, |_, _| true);
+
+    let mut stops = Vec::new();
+    for _ in 0..3 {
+        dvm.run(Some(100)).unwrap();
+        assert_eq!(dvm.pc, 0x1001);
+        stops.push(dvm.stack.clone());
+    }
+
+    assert_eq!(stops, vec![vec![3], vec![2], vec![1]]);
+}
+
+#[test]
+fn redirect() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_lit2; DW_OP_lit3
+    dvm.overlay().insert(0x1000, vec![0x31, 0x32, 0x33]);
+    dvm.set_breakpoint(0x1001, |dvm, _| {
+        dvm.pc = 0x1002;
+        false
+    });
+
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.pc, 0x1003);
+    assert_eq!(dvm.stack, vec![1, 3]);
+}
+
+#[test]
+fn assert_at() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_lit2; DW_OP_plus
+    dvm.overlay().insert(0x1000, vec![0x31, 0x32, 0x22]);
+    dvm.assert_at(0x1002, &[1, 2]);
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![3]);
+
+    dvm.reset();
+    dvm.assert_at(0x1002, &[3]);
+    assert_eq!(
+        dvm.run(Some(3)),
+        Err(DwarfVmError::AssertionFailed {
+            pc: 0x1002,
+            expected: vec![3],
+            actual: vec![2],
+        })
+    );
+}
+
+#[test]
+fn ext_op() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::lit(2), asm::stack_value()];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.set_breakpoint(0x1001, |_, _| true);
+
+    assert_eq!(dvm.run(Some(10)), Ok(1));
+    assert_eq!(dvm.pc, 0x1001);
+    assert_eq!(dvm.result(), Some(ExprResult::Address(2)));
+
+    assert_eq!(dvm.run(Some(1)), Ok(1));
+    assert_eq!(dvm.result(), Some(ExprResult::Value(2)));
+
+    dvm.reset();
+    dvm.assert_at(0x1001, &[3]);
+    assert_eq!(
+        dvm.run(Some(2)),
+        Err(DwarfVmError::AssertionFailed {
+            pc: 0x1001,
+            expected: vec![3],
+            actual: vec![2],
+        })
+    );
+}
+
+#[test]
+fn nop_hook() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::nop(), asm::lit(1), asm::nop(), asm::lit(2)];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    // stop at the second nop
+    let mut nops = 0;
+    dvm.set_nop_hook(move |_| {
+        nops += 1;
+
+        if nops == 2 {
+            ControlFlow::Break(())
+        } else {
+            ControlFlow::Continue(())
+        }
+    });
+
+    assert_eq!(dvm.step(), Ok(()));
+    assert_eq!(dvm.step(), Ok(()));
+    assert_eq!(dvm.step(), Err(DwarfVmError::Breakpoint { pc: 0x1003 }));
+    assert_eq!(dvm.insn_count(), 3);
+
+    assert_eq!(dvm.step(), Ok(()));
+    assert_eq!(dvm.stack, vec![1, 2]);
+}
@@ -0,0 +1,80 @@
+use iris::{asm, DwarfVm, DwarfVmError, StopReason};
+
+#[test]
+fn call() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let sub = [asm::lit(2), asm::plus()].concat();
+    let base = dvm.map_program_aligned(&sub, 0x2000, 0x10).unwrap();
+    dvm.add_call_target(0x2a, base, base + sub.len() as u64);
+
+    let prog = [asm::lit(1), asm::call2(0x2a), asm::lit(5)];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.pc, 0x2000);
+    assert_eq!(dvm.call_depth(), 1);
+
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.pc, 0x1005);
+    assert_eq!(dvm.call_depth(), 0);
+    assert_eq!(dvm.stack, vec![3, 5]);
+}
+
+#[test]
+fn call_returns_with_last_op() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // the callee ends exactly where its mapping does
+    let sub = [asm::lit(2), asm::plus()].concat();
+    dvm.overlay().insert(0x2000, sub.clone());
+    dvm.add_call_target(0x2a, 0x2000, 0x2000 + sub.len() as u64);
+
+    let prog = [asm::lit(1), asm::call2(0x2a), asm::lit(5)];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    let next_pcs = (0..5)
+        .map(|_| dvm.step_event().unwrap().next_pc)
+        .collect::<Vec<_>>();
+    assert_eq!(next_pcs, vec![0x1001, 0x2000, 0x2001, 0x1004, 0x1005]);
+
+    dvm.reset();
+    dvm.run(Some(4)).unwrap();
+    assert_eq!(dvm.pc, 0x1004);
+    assert_eq!(dvm.call_depth(), 0);
+    assert_eq!(dvm.current_op_bytes().unwrap(), &asm::lit(5)[..]);
+
+    dvm.reset();
+    let summary = dvm.run_summary(None);
+    assert_eq!(summary.instructions, 5);
+    assert_eq!(summary.stop_reason, StopReason::ProgramEnd);
+    assert_eq!(dvm.stack, vec![3, 5]);
+}
+
+#[test]
+fn call_depth() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_max_call_depth(8);
+
+    // two expressions which call each other forever
+    dvm.overlay().insert(0x2000, asm::call4(0xb));
+    dvm.overlay().insert(0x3000, asm::call2(0xa));
+    dvm.add_call_target(0xa, 0x2000, 0x2005);
+    dvm.add_call_target(0xb, 0x3000, 0x3003);
+
+    dvm.overlay().insert(0x1000, asm::call2(0xa));
+
+    assert_eq!(
+        dvm.run(None),
+        Err(DwarfVmError::CallDepthExceeded { pc: 0x3000 })
+    );
+    assert_eq!(dvm.call_depth(), 8);
+
+    // unknown DIEs are unsupported
+    dvm.overlay().insert(0x1000, asm::call2(0xc));
+    dvm.reset();
+    assert_eq!(dvm.step(), Err(DwarfVmError::UnsupportedOp { pc: 0x1000 }));
+}
@@ -0,0 +1,55 @@
+use iris::DwarfVm;
+
+fn eval(prog: &[u8]) -> Vec<u64> {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    dvm.overlay().insert(0x1000, prog.to_vec());
+    dvm.step().unwrap();
+
+    dvm.stack
+}
+
+#[test]
+fn unsigned_zero_extends() {
+    // DW_OP_const1u 0xff
+    assert_eq!(eval(&[0x08, 0xff]), vec![0xff]);
+    // DW_OP_const2u 0xffff
+    assert_eq!(eval(&[0x0a, 0xff, 0xff]), vec![0xffff]);
+    // DW_OP_const4u 0xffffffff
+    assert_eq!(
+        eval(&[0x0c, 0xff, 0xff, 0xff, 0xff]),
+        vec![0x0000_0000_ffff_ffff]
+    );
+}
+
+#[test]
+fn signed_sign_extends() {
+    // DW_OP_const1s -1
+    assert_eq!(eval(&[0x09, 0xff]), vec![u64::MAX]);
+    // DW_OP_const2s -1
+    assert_eq!(eval(&[0x0b, 0xff, 0xff]), vec![u64::MAX]);
+    // DW_OP_const4s -1
+    assert_eq!(
+        eval(&[0x0d, 0xff, 0xff, 0xff, 0xff]),
+        vec![0xffff_ffff_ffff_ffff]
+    );
+    // DW_OP_consts -2
+    assert_eq!(eval(&[0x11, 0x7e]), vec![-2i64 as u64]);
+}
+
+#[test]
+fn lit_is_unsigned() {
+    // DW_OP_lit0 and DW_OP_lit31
+    assert_eq!(eval(&[0x30]), vec![0]);
+    assert_eq!(eval(&[0x4f]), vec![31]);
+}
+
+#[test]
+fn addr_pushes_address() {
+    // DW_OP_addr 0x1000
+    assert_eq!(
+        eval(&[0x03, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]),
+        vec![0x1000]
+    );
+}
@@ -0,0 +1,169 @@
+use iris::{asm, Capability, DwarfVm, DwarfVmError};
+
+#[test]
+fn decode_resync() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // two bad bytes then DW_OP_lit1
+    dvm.overlay().insert(0x1000, vec![0x00, 0x00, 0x31]);
+
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::Decode {
+            pc: 0x1000,
+            bytes: vec![0x00, 0x00, 0x31]
+        })
+    );
+
+    dvm.set_decode_resync(true);
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.pc, 0x1003);
+    assert_eq!(dvm.stack, vec![1]);
+    assert_eq!(dvm.resync_skipped(), 2);
+}
+
+#[test]
+fn op_boundaries() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_const2u 0x1234; DW_OP_plus_uconst 0x80; DW_OP_plus
+    dvm.overlay()
+        .insert(0x1000, vec![0x31, 0x0a, 0x34, 0x12, 0x23, 0x80, 0x01, 0x22]);
+
+    assert_eq!(
+        dvm.op_boundaries(0x1000, 0x1008),
+        Ok(vec![0x1000, 0x1001, 0x1004, 0x1007])
+    );
+    assert_eq!(dvm.op_boundaries(0x1001, 0x1002), Ok(vec![0x1001]));
+}
+
+#[test]
+fn static_stack_delta() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_dup; DW_OP_lit2; DW_OP_plus; DW_OP_skip 0
+    dvm.overlay()
+        .insert(0x1000, vec![0x31, 0x12, 0x32, 0x22, 0x2f, 0x00, 0x00]);
+
+    assert_eq!(dvm.static_stack_delta(0x1000, 0x1004), Ok(2));
+    assert_eq!(dvm.static_stack_delta(0x1003, 0x1004), Ok(-1));
+    assert_eq!(
+        dvm.static_stack_delta(0x1000, 0x1007),
+        Err(DwarfVmError::NoStaticDelta { pc: 0x1004 })
+    );
+}
+
+#[test]
+fn op_operands() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::bregx(6, -8), asm::pick(3), asm::plus()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    let mut operands = Vec::new();
+    for _ in 0..3 {
+        let (sz, op) = dvm.peek_op().unwrap();
+        operands.push(iris::op_operands(&op));
+        dvm.pc += sz;
+    }
+
+    assert_eq!(
+        operands,
+        vec![vec![("reg", 6), ("offset", -8)], vec![("index", 3)], vec![]]
+    );
+}
+
+#[test]
+fn decode_all() {
+    let prog = [asm::bregx(6, -8), asm::pick(3), asm::plus()].concat();
+
+    let ops = iris::decode_all(&prog)
+        .map(|x| x.map(|(sz, op)| (sz, iris::op_operands(&op))))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+
+    assert_eq!(
+        ops,
+        vec![
+            (3, vec![("reg", 6), ("offset", -8)]),
+            (2, vec![("index", 3)]),
+            (1, vec![])
+        ]
+    );
+
+    // a bad byte ends decoding
+    let prog = [asm::lit(1), vec![0x00, 0x00], asm::lit(2)].concat();
+    let ops = iris::decode_all(&prog).collect::<Vec<_>>();
+
+    assert_eq!(ops.len(), 2);
+    assert!(ops[0].is_ok());
+    assert_eq!(
+        ops[1].as_ref().err(),
+        Some(&DwarfVmError::Decode {
+            pc: 1,
+            bytes: vec![0x00, 0x00, 0x32]
+        })
+    );
+
+    // so does an extended op
+    let prog = [asm::lit(1), asm::stack_value()].concat();
+    let ops = iris::decode_all(&prog).collect::<Vec<_>>();
+
+    assert_eq!(ops.len(), 2);
+    assert_eq!(
+        ops[1].as_ref().err(),
+        Some(&DwarfVmError::Decode {
+            pc: 1,
+            bytes: vec![0x9f]
+        })
+    );
+}
+
+#[test]
+fn capability_report() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_addrx 0
+    let prog = [asm::lit(1), asm::form_tls_address(), vec![0xa1, 0x00]];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    let report = dvm.capability_report(0x1000, 0x1004).unwrap();
+    assert_eq!(
+        report.ops,
+        vec![
+            (0x1000, Capability::Supported),
+            (0x1001, Capability::RequiresConfig("tls base")),
+            (0x1002, Capability::Unimplemented),
+        ]
+    );
+    assert!(!report.runnable());
+
+    dvm.set_tls_base(0x10000);
+    let report = dvm.capability_report(0x1000, 0x1002).unwrap();
+    assert!(report.runnable());
+}
+
+#[test]
+fn skip_unknown() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_addrx 0; DW_OP_lit2
+    dvm.overlay().insert(0x1000, vec![0x31, 0xa1, 0x00, 0x32]);
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::UnsupportedOp { pc: 0x1001 }));
+    assert_eq!(dvm.pc, 0x1001);
+    assert_eq!(dvm.stack, vec![1]);
+
+    dvm.set_skip_unknown(true);
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.pc, 0x1004);
+    assert_eq!(dvm.stack, vec![1, 2]);
+}
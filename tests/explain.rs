@@ -0,0 +1,89 @@
+use iris::{DwarfVm, DwarfVmState, MemRead};
+
+#[test]
+fn explain_step() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_lit2; DW_OP_plus; DW_OP_bra +0
+    dvm.overlay()
+        .insert(0x1000, vec![0x31, 0x32, 0x22, 0x28, 0x00, 0x00]);
+
+    let _ = dvm.explain_step().unwrap();
+    let _ = dvm.explain_step().unwrap();
+
+    let (desc, state) = dvm.explain_step().unwrap();
+    assert!(
+        desc.ends_with(": popped 0x2, popped 0x1, pushed 0x3"),
+        "{}",
+        desc
+    );
+    assert_eq!(state, DwarfVmState::new(0x1003, vec![3]));
+
+    let (desc, _) = dvm.explain_step().unwrap();
+    assert!(desc.ends_with(": popped 0x3"), "{}", desc);
+}
+
+#[test]
+fn step_event() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_const2u 0x2000; DW_OP_deref
+    dvm.overlay().insert(0x1000, vec![0x0a, 0x00, 0x20, 0x06]);
+    dvm.overlay().insert(0x2000, 0x42u64.to_le_bytes().to_vec());
+
+    dvm.step().unwrap();
+    let event = dvm.step_event().unwrap();
+
+    assert_eq!(event.pc, 0x1003);
+    assert_eq!(event.next_pc, 0x1004);
+    assert_eq!(event.popped, vec![0x2000]);
+    assert_eq!(event.pushed, vec![0x42]);
+    assert_eq!(
+        event.reads,
+        vec![MemRead {
+            addr: 0x2000,
+            size: 8,
+            value: 0x42,
+            synthetic: false
+        }]
+    );
+}
+
+#[test]
+fn dump() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_stack_value
+    dvm.overlay().insert(0x1000, vec![0x31, 0x9f]);
+    dvm.set_breakpoint(0x1001, |_, _| false);
+    dvm.step().unwrap();
+
+    let terse = "DwarfVm { pc: 0x1001, stack: [1] }\nop: stack_value\n";
+    assert_eq!(dvm.dump(false), terse);
+
+    assert_eq!(
+        dvm.dump(true),
+        [
+            terse,
+            "ctx: 0x0\n",
+            "entry: 0x1000\n",
+            "insn count: 1\n",
+            "reads: 0\n",
+            "overlay regions: 1\n",
+            "breakpoints: [1001]\n",
+            "stack depth: 1\n",
+            "00 | 0000000000000001\n",
+        ]
+        .concat()
+    );
+
+    // an op which can't be fetched is reported in its place
+    dvm.pc = 0x1234;
+    assert_eq!(
+        dvm.dump(false),
+        "DwarfVm { pc: 0x1234, stack: [1] }\nop: <address 0x1234 is not mapped>\n"
+    );
+}
@@ -0,0 +1,86 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use iris::{asm, DwarfVm, DwarfVmError, StackEvent};
+
+#[test]
+fn step_hooks() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::lit(1), asm::lit(2), asm::plus()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    // (pc, stack depth) before and after each op
+    let pre = Rc::new(RefCell::new(Vec::new()));
+    let post = Rc::new(RefCell::new(Vec::new()));
+
+    let log = pre.clone();
+    dvm.set_pre_step(move |dvm, _| log.borrow_mut().push((dvm.pc, dvm.stack.len())));
+
+    let log = post.clone();
+    dvm.set_post_step(move |dvm, _| log.borrow_mut().push((dvm.pc, dvm.stack.len())));
+
+    dvm.run(Some(3)).unwrap();
+
+    assert_eq!(*pre.borrow(), vec![(0x1000, 0), (0x1001, 1), (0x1002, 2)]);
+    assert_eq!(*post.borrow(), vec![(0x1001, 1), (0x1002, 2), (0x1003, 1)]);
+}
+
+#[test]
+fn stack_observer() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::lit(1), asm::lit(2), asm::swap()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+
+    let log = events.clone();
+    dvm.set_stack_observer(move |e| log.borrow_mut().push(e));
+
+    dvm.run(Some(3)).unwrap();
+
+    assert_eq!(
+        *events.borrow(),
+        vec![
+            StackEvent::Push(1),
+            StackEvent::Push(2),
+            StackEvent::Pop(2),
+            StackEvent::Pop(1),
+            StackEvent::Push(2),
+            StackEvent::Push(1),
+        ]
+    );
+}
+
+#[test]
+fn boundary_read_hook() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let reads = Rc::new(RefCell::new(Vec::new()));
+    let seen = reads.clone();
+    dvm.set_boundary_read_hook(move |addr, len, sz| seen.borrow_mut().push((addr, len, sz)));
+
+    // patch half of a u64 on the stack
+    dvm.overlay().insert(0x7fffffe10c, vec![0; 4]);
+
+    let prog = [
+        asm::const8u(0x7fffffe10c),
+        asm::deref_size(4),
+        asm::const8u(0x7fffffe10c),
+        asm::deref(),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.run(Some(3)).unwrap();
+    assert!(reads.borrow().is_empty());
+
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::Unmapped { addr: 0x7fffffe10c })
+    );
+    assert_eq!(*reads.borrow(), vec![(0x7fffffe10c, 4, 8)]);
+}
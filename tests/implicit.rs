@@ -0,0 +1,243 @@
+use iris::{
+    asm, DwarfVm, DwarfVmError, EvalConfig, Evaluation, ExpectedResult, ExprResult, Piece,
+    PieceLocation, MAX_COMPOSITE_SIZE,
+};
+
+#[test]
+fn implicit_value() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_implicit_value 4 [de ad be ef]
+    dvm.overlay()
+        .insert(0x1000, vec![0x9e, 0x04, 0xde, 0xad, 0xbe, 0xef]);
+
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.pc, 0x1006);
+    assert_eq!(
+        dvm.result(),
+        Some(ExprResult::ImplicitBytes(vec![0xde, 0xad, 0xbe, 0xef]))
+    );
+}
+
+#[test]
+fn stack_value() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::lit(2), asm::lit(3), asm::plus(), asm::stack_value()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    assert_eq!(dvm.eval_address(Some(4)), Err(DwarfVmError::NotAnAddress));
+    assert_eq!(dvm.result(), Some(ExprResult::Value(5)));
+
+    dvm.reset();
+    assert_eq!(dvm.eval_address(Some(3)), Ok(5));
+}
+
+#[test]
+fn assemble_pieces() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [
+        // a byte on the stack
+        asm::const1u(0xab),
+        asm::stack_value(),
+        asm::piece(1),
+        // two bytes in memory
+        asm::const2u(0x2000),
+        asm::piece(2),
+        // the high nibble of an implicit value
+        asm::implicit_value(&[0x56]),
+        asm::bit_piece(4, 4),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.overlay().insert(0x2000, vec![0x34, 0x12]);
+
+    dvm.run(Some(7)).unwrap();
+
+    assert_eq!(
+        dvm.pieces(),
+        &[
+            Piece {
+                size: 8,
+                offset: 0,
+                location: PieceLocation::Value(0xab),
+            },
+            Piece {
+                size: 16,
+                offset: 0,
+                location: PieceLocation::Memory(0x2000),
+            },
+            Piece {
+                size: 4,
+                offset: 4,
+                location: PieceLocation::Implicit(vec![0x56]),
+            },
+        ]
+    );
+    assert_eq!(dvm.assemble_pieces(), Ok(vec![0xab, 0x34, 0x12, 0x05]));
+}
+
+#[test]
+fn optimized_out_piece() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [
+        asm::lit(1),
+        asm::stack_value(),
+        asm::piece(1),
+        asm::piece(1),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.run(Some(4)).unwrap();
+
+    assert_eq!(
+        dvm.assemble_pieces(),
+        Err(DwarfVmError::OptimizedOut { piece: 1 })
+    );
+}
+
+#[test]
+fn composite_too_large() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // the piece size in bits doesn't fit in a u64
+    dvm.overlay().insert(0x1000, asm::piece(u64::MAX / 4));
+    assert_eq!(dvm.step(), Err(DwarfVmError::CompositeTooLarge));
+    assert!(dvm.pieces().is_empty());
+
+    // each piece fits, but together they're too large
+    let prog = [asm::piece(MAX_COMPOSITE_SIZE), asm::bit_piece(1, 0)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.reset();
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::CompositeTooLarge));
+    assert_eq!(dvm.pieces().len(), 1);
+
+    // the piece's last bit isn't addressable
+    dvm.overlay().insert(0x1000, asm::bit_piece(2, u64::MAX));
+    dvm.reset();
+    assert_eq!(dvm.step(), Err(DwarfVmError::CompositeTooLarge));
+}
+
+#[test]
+fn finalize() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    assert_eq!(
+        dvm.finalize(ExpectedResult::Value),
+        Err(DwarfVmError::UnbalancedStack { remaining: 0 })
+    );
+
+    let prog = [asm::lit(2), asm::stack_value()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.run(Some(1)).unwrap();
+    assert_eq!(
+        dvm.finalize(ExpectedResult::Address),
+        Ok(ExprResult::Address(2))
+    );
+    assert_eq!(
+        dvm.finalize(ExpectedResult::Value),
+        Err(DwarfVmError::NotAValue)
+    );
+
+    dvm.run(Some(1)).unwrap();
+    assert_eq!(
+        dvm.finalize(ExpectedResult::Address),
+        Err(DwarfVmError::NotAnAddress)
+    );
+    assert_eq!(
+        dvm.finalize(ExpectedResult::Value),
+        Ok(ExprResult::Value(2))
+    );
+}
+
+#[test]
+fn register_location() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::from_thread(0x1000, core, 0).unwrap();
+
+    dvm.overlay().insert(0x1000, asm::reg(25));
+    dvm.run(Some(1)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0x7fffffe110]);
+    assert_eq!(dvm.result(), Some(ExprResult::Register(25)));
+    assert_eq!(
+        dvm.finalize(ExpectedResult::Value),
+        Err(DwarfVmError::NotAValue)
+    );
+
+    dvm.reset();
+    assert_eq!(dvm.eval_address(Some(1)), Err(DwarfVmError::NotAnAddress));
+
+    // a register used as an operand is not a location
+    let prog = [asm::reg(25), asm::lit(8), asm::plus()];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.reset();
+    assert_eq!(dvm.eval_address(Some(3)), Ok(0x7fffffe118));
+
+    // a register piece
+    let prog = [asm::reg(25), asm::piece(8)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.reset();
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(
+        dvm.pieces()[0].location,
+        PieceLocation::Register {
+            reg: 25,
+            value: 0x7fffffe110
+        }
+    );
+    assert_eq!(
+        dvm.assemble_pieces(),
+        Ok(0x7fffffe110u64.to_le_bytes().to_vec())
+    );
+}
+
+#[test]
+fn evaluate() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::lit(2), asm::stack_value()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    let cfg = EvalConfig {
+        limit: Some(2),
+        ..EvalConfig::default()
+    };
+    assert_eq!(dvm.evaluate(cfg), Ok(Evaluation::Value(2)));
+
+    dvm.reset();
+    let cfg = EvalConfig {
+        limit: Some(1),
+        expect: Some(ExpectedResult::Value),
+    };
+    assert_eq!(dvm.evaluate(cfg), Err(DwarfVmError::NotAValue));
+
+    let prog = [asm::lit(2), asm::piece(4), asm::lit(3), asm::piece(4)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.reset();
+
+    let cfg = EvalConfig {
+        limit: Some(4),
+        expect: Some(ExpectedResult::Address),
+    };
+    match dvm.evaluate(cfg) {
+        Ok(Evaluation::Composite(pieces)) => {
+            assert_eq!(pieces.len(), 2);
+            assert_eq!(pieces[1].location, PieceLocation::Memory(3));
+        }
+        r => panic!("expected a composite, got {:?}", r),
+    }
+}
@@ -0,0 +1,22 @@
+#![cfg(feature = "linux-live")]
+
+use iris::{asm, DwarfVm, ProcMem};
+
+#[test]
+fn op_straddles_page() {
+    let mut buf = vec![0u8; 0x3000];
+
+    // put a const8u so its operand runs onto the next page
+    let page = (buf.as_ptr() as u64 + 0xfff) & !0xfff;
+    let pc = page + 0x1000 - 3;
+    let off = (pc - buf.as_ptr() as u64) as usize;
+
+    let op = asm::const8u(0x1122_3344_5566_7788);
+    buf[off..off + op.len()].copy_from_slice(&op);
+
+    let mut dvm = DwarfVm::without_memory(pc, 0);
+    dvm.set_live_memory(ProcMem::open(std::process::id()).unwrap());
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.stack, vec![0x1122_3344_5566_7788]);
+}
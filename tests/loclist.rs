@@ -0,0 +1,156 @@
+use iris::{asm, DwarfVm, DwarfVmError, EXPR_BASE};
+
+/// Build an ELF holding just the section `name`, with contents `data`
+fn core_with(name: &str, data: &[u8]) -> Vec<u8> {
+    let strtab = [&b"\0.shstrtab\0"[..], name.as_bytes(), b"\0"].concat();
+
+    let data_off = 0x40;
+    let strtab_off = data_off + data.len();
+    let shoff = (strtab_off + strtab.len() + 7) & !7;
+
+    let mut core = vec![0u8; shoff];
+
+    // ELF64, little endian, ET_CORE, EM_AARCH64
+    core[..8].copy_from_slice(&[0x7f, b'E', b'L', b'F', 2, 1, 1, 0]);
+    core[0x10..0x12].copy_from_slice(&4u16.to_le_bytes());
+    core[0x12..0x14].copy_from_slice(&183u16.to_le_bytes());
+    core[0x14..0x18].copy_from_slice(&1u32.to_le_bytes());
+    core[0x28..0x30].copy_from_slice(&(shoff as u64).to_le_bytes());
+    core[0x34..0x36].copy_from_slice(&0x40u16.to_le_bytes());
+    core[0x36..0x38].copy_from_slice(&0x38u16.to_le_bytes());
+    core[0x3a..0x3c].copy_from_slice(&0x40u16.to_le_bytes());
+    core[0x3c..0x3e].copy_from_slice(&3u16.to_le_bytes());
+    core[0x3e..0x40].copy_from_slice(&2u16.to_le_bytes());
+
+    core[data_off..strtab_off].copy_from_slice(data);
+    core[strtab_off..strtab_off + strtab.len()].copy_from_slice(&strtab);
+
+    // (name, type, offset, size) of the null, data and string table sections
+    let sections = [
+        (0u32, 0u32, 0usize, 0usize),
+        (11, 1, data_off, data.len()),
+        (1, 3, strtab_off, strtab.len()),
+    ];
+
+    for &(name, ty, off, size) in &sections {
+        let mut shdr = [0u8; 0x40];
+        shdr[0x00..0x04].copy_from_slice(&name.to_le_bytes());
+        shdr[0x04..0x08].copy_from_slice(&ty.to_le_bytes());
+        shdr[0x18..0x20].copy_from_slice(&(off as u64).to_le_bytes());
+        shdr[0x20..0x28].copy_from_slice(&(size as u64).to_le_bytes());
+
+        core.extend_from_slice(&shdr);
+    }
+
+    core
+}
+
+fn loaded(dvm: &mut DwarfVm) -> Vec<u8> {
+    dvm.overlay()[&EXPR_BASE].clone()
+}
+
+#[test]
+fn debug_loc() {
+    let entry = |begin: u64, end: u64, expr: Vec<u8>| {
+        [
+            begin.to_le_bytes().to_vec(),
+            end.to_le_bytes().to_vec(),
+            (expr.len() as u16).to_le_bytes().to_vec(),
+            expr,
+        ]
+        .concat()
+    };
+
+    let list = [
+        entry(0x10, 0x20, asm::lit(1)),
+        // base address selection
+        [u64::MAX.to_le_bytes(), 0x5000u64.to_le_bytes()].concat(),
+        entry(0x10, 0x20, asm::lit(2)),
+        // end of list
+        vec![0; 16],
+    ]
+    .concat();
+
+    let core = core_with(".debug_loc", &list);
+    let mut dvm = DwarfVm::new(0, 0, &core);
+
+    dvm.load_location_list(0, 0x1018, 0x1000).unwrap();
+    assert_eq!(loaded(&mut dvm), asm::lit(1));
+    assert_eq!(dvm.pc, EXPR_BASE);
+
+    dvm.load_location_list(0, 0x5018, 0x1000).unwrap();
+    assert_eq!(loaded(&mut dvm), asm::lit(2));
+
+    // offsets are relative to the CU base
+    assert_eq!(
+        dvm.load_location_list(0, 0x18, 0x1000),
+        Err(DwarfVmError::NoLocation { target_pc: 0x18 })
+    );
+    dvm.load_location_list(0, 0x18, 0).unwrap();
+    assert_eq!(loaded(&mut dvm), asm::lit(1));
+
+    // a list cut off before its end of list entry
+    let core = core_with(".debug_loc", &list[..list.len() - 4]);
+    let mut dvm = DwarfVm::new(0, 0, &core);
+    assert_eq!(
+        dvm.load_location_list(0, 0x18, 0x1000),
+        Err(DwarfVmError::BadLocList)
+    );
+}
+
+#[test]
+fn debug_loclists() {
+    let list = [
+        // DW_LLE_offset_pair
+        vec![0x04],
+        asm::uleb(0x10),
+        asm::uleb(0x20),
+        asm::uleb(1),
+        asm::lit(1),
+        // DW_LLE_base_address
+        vec![0x06],
+        0x5000u64.to_le_bytes().to_vec(),
+        // DW_LLE_offset_pair
+        vec![0x04],
+        asm::uleb(0x10),
+        asm::uleb(0x20),
+        asm::uleb(1),
+        asm::lit(2),
+        // DW_LLE_default_location
+        vec![0x05],
+        asm::uleb(1),
+        asm::lit(3),
+        // DW_LLE_end_of_list
+        vec![0x00],
+    ]
+    .concat();
+
+    // a second list with no entries at all
+    let data = [list.clone(), vec![0x00]].concat();
+
+    let core = core_with(".debug_loclists", &data);
+    let mut dvm = DwarfVm::new(0, 0, &core);
+
+    dvm.load_location_list(0, 0x1018, 0x1000).unwrap();
+    assert_eq!(loaded(&mut dvm), asm::lit(1));
+
+    dvm.load_location_list(0, 0x5018, 0x1000).unwrap();
+    assert_eq!(loaded(&mut dvm), asm::lit(2));
+
+    // nothing covers the PC, so the default location is used
+    dvm.load_location_list(0, 0x1018, 0).unwrap();
+    assert_eq!(loaded(&mut dvm), asm::lit(3));
+
+    assert_eq!(
+        dvm.load_location_list(list.len() as u64, 0x1018, 0x1000),
+        Err(DwarfVmError::NoLocation { target_pc: 0x1018 })
+    );
+
+    // a list cut off before its end of list entry
+    let core = core_with(".debug_loclists", &list[..list.len() - 1]);
+    let mut dvm = DwarfVm::new(0, 0, &core);
+    assert_eq!(
+        dvm.load_location_list(0, 0x18, 0x1000),
+        Err(DwarfVmError::BadLocList)
+    );
+}
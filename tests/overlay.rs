@@ -0,0 +1,304 @@
+use std::cell::RefCell;
+use std::ops::ControlFlow;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use iris::{asm, DwarfVm, DwarfVmError};
+
+#[test]
+fn overlay_diff() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut a = DwarfVm::new(0x400258, 0x7fffffe110, core);
+    let mut b = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    a.overlay().insert(0x1000, vec![1, 2, 3, 4]);
+    b.overlay().insert(0x1000, vec![1, 2, 9, 4]);
+
+    // only in a, compared against b's core
+    a.overlay().insert(0x400258, vec![0x6f, 0x00]);
+
+    // only in b, and unmapped in a
+    b.overlay().insert(0x1234, vec![7]);
+
+    // borrowed mappings are not walked
+    a.map_borrowed(0x5000, &[1, 2, 3]);
+
+    assert_eq!(
+        DwarfVm::overlay_diff(&a, &b),
+        vec![
+            (0x1002, Some(3), Some(9)),
+            (0x1234, None, Some(7)),
+            (0x400259, Some(0x00), Some(0x08))
+        ]
+    );
+}
+
+#[test]
+fn overlay_snapshot() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    dvm.overlay().insert(0x1000, vec![1, 2, 3, 4]);
+    let snap = dvm.overlay_snapshot();
+
+    dvm.overlay().insert(0x2000, vec![5]);
+    dvm.restore_overlay(&snap);
+
+    assert_eq!(
+        dvm.overlay_regions().collect::<Vec<_>>(),
+        vec![(0x1000, &[1u8, 2, 3, 4][..])]
+    );
+}
+
+#[test]
+fn new_with_program() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let prog = [asm::const4u(0x400258), asm::deref_size(1)].concat();
+    let mut dvm = DwarfVm::new_with_program(0x1000, prog, 0, core);
+
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.pc, 0x1007);
+    assert_eq!(dvm.stack, vec![0x6f]);
+}
+
+#[test]
+fn map_borrowed() {
+    let core = include_bytes!("../res/entry2.core");
+    let heap = vec![0x41u8; 0x1000];
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.map_borrowed(0x10_0000, &heap);
+
+    let prog = [
+        asm::const4u(0x10_0800),
+        asm::deref_size(1),
+        asm::const4u(0x10_0801),
+        asm::deref_size(1),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    // the owned overlay takes priority
+    dvm.overlay().insert(0x10_0801, vec![0x42]);
+
+    dvm.run(Some(4)).unwrap();
+    assert_eq!(dvm.stack, vec![0x41, 0x42]);
+}
+
+#[test]
+fn load_location_expr() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    dvm.stack.push(7);
+
+    let expr = [asm::lit(2), asm::lit(3), asm::mul()].concat();
+    dvm.load_location_expr(&expr, 0x2000);
+
+    assert_eq!(dvm.run_to_result(Some(3)), Ok(6));
+
+    dvm.reset();
+    assert_eq!(dvm.pc, 0x2000);
+}
+
+#[test]
+fn without_memory() {
+    let mut dvm = DwarfVm::without_memory(0x1000, 0);
+
+    let prog = [
+        asm::const2u(0x2000),
+        asm::deref_size(1),
+        asm::const2u(0x3000),
+        asm::deref_size(1),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.overlay().insert(0x2000, vec![0x42]);
+
+    assert!(dvm.threads().is_empty());
+
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![0x42, 0x3000]);
+
+    assert_eq!(dvm.step(), Err(DwarfVmError::Unmapped { addr: 0x3000 }));
+}
+
+#[test]
+fn new_owned() {
+    struct Session {
+        dvm: DwarfVm<'static>,
+    }
+
+    let core = include_bytes!("../res/entry2.core").to_vec();
+    let mut session = Session {
+        dvm: DwarfVm::new_owned(0x1000, 0, core).unwrap(),
+    };
+
+    let prog = [asm::const4u(0x400258), asm::deref_size(1)].concat();
+    session.dvm.overlay().insert(0x1000, prog);
+    session.dvm.run(Some(2)).unwrap();
+
+    assert_eq!(session.dvm.stack, vec![0x6f]);
+
+    // the core is shared rather than copied, and a bad one is an error
+    let core: Arc<[u8]> = include_bytes!("../res/entry2.core").to_vec().into();
+    let dvm = DwarfVm::new_owned(0x1000, 0, core.clone()).unwrap();
+    assert_eq!(Arc::strong_count(&core), 2);
+    drop(dvm);
+    assert_eq!(Arc::strong_count(&core), 1);
+
+    assert!(DwarfVm::new_owned(0x1000, 0, vec![0u8; 16]).is_err());
+}
+
+#[test]
+fn load_exprloc() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    // the expression is followed by an op which must not run
+    let expr = [asm::lit(2), asm::lit(3), asm::mul()].concat();
+    let exprloc = [asm::uleb(expr.len() as u64), expr, asm::lit(1)].concat();
+
+    dvm.load_exprloc(&exprloc, 0x2000).unwrap();
+
+    assert_eq!(dvm.run(None), Err(DwarfVmError::Unmapped { addr: 0x2003 }));
+    assert_eq!(dvm.stack, vec![6]);
+
+    assert_eq!(
+        dvm.load_exprloc(&[0x04, 0x32], 0x2000),
+        Err(DwarfVmError::BadExprLoc)
+    );
+
+    // the end of the expression would wrap, nothing is loaded
+    assert_eq!(
+        dvm.load_exprloc(&exprloc, u64::MAX - 1),
+        Err(DwarfVmError::BadExprLoc)
+    );
+    assert_eq!(dvm.pc, 0x2003);
+}
+
+#[test]
+fn save_load() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0x7fffffe110, core);
+    dvm.set_addr_size(4);
+
+    let prog = [
+        asm::lit(2),
+        asm::lit(3),
+        asm::mul(),
+        asm::lit(1),
+        asm::plus(),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(3)).unwrap();
+
+    let mut saved = Vec::new();
+    dvm.save(&mut saved).unwrap();
+
+    let mut loaded = DwarfVm::load(&mut &saved[..], core).unwrap();
+    assert_eq!(loaded.state(), dvm.state());
+    assert_eq!(loaded.ctx(), 0x7fffffe110);
+    assert_eq!(loaded.insn_count(), 3);
+    assert_eq!(loaded.addr_size(), 4);
+
+    loaded.run(Some(2)).unwrap();
+    assert_eq!(loaded.stack, vec![7]);
+
+    loaded.reset();
+    assert_eq!(loaded.pc, 0x1000);
+
+    assert_eq!(
+        DwarfVm::load(&mut &saved[..saved.len() - 1], core).err(),
+        Some(DwarfVmError::BadSnapshot)
+    );
+}
+
+#[test]
+fn map_program_aligned() {
+    let mut dvm = DwarfVm::without_memory(0, 0);
+
+    let prog = [asm::lit(2), asm::lit(3), asm::mul()].concat();
+
+    assert_eq!(dvm.map_program_aligned(&prog, 0x1000, 0x10), Some(0x1000));
+    assert_eq!(dvm.map_program_aligned(&prog, 0x1003, 0x10), Some(0x1010));
+    assert_eq!(dvm.map_program_aligned(&prog, 0x1013, 1), Some(0x1013));
+
+    // no 0x10 aligned address is left past u64::MAX - 3
+    assert_eq!(dvm.map_program_aligned(&prog, u64::MAX - 3, 0x10), None);
+    assert_eq!(dvm.overlay().len(), 3);
+
+    dvm.pc = 0x1010;
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![6]);
+}
+
+#[test]
+fn escape_hook() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    // jump from the expression to shared code at 0x3000
+    let expr = [asm::lit(1), asm::skip(0x3000 - 0x2004)].concat();
+    let exprloc = [asm::uleb(expr.len() as u64), expr].concat();
+    dvm.overlay().insert(0x3000, asm::lit(2));
+
+    dvm.load_exprloc(&exprloc, 0x2000).unwrap();
+    assert_eq!(
+        dvm.run(None),
+        Err(DwarfVmError::BranchOutOfRange {
+            pc: 0x2001,
+            target: 0x3000
+        })
+    );
+
+    let escapes = Rc::new(RefCell::new(Vec::new()));
+    let seen = escapes.clone();
+    dvm.set_escape_hook(move |_, target| {
+        seen.borrow_mut().push(target);
+        ControlFlow::Continue(())
+    });
+
+    dvm.reset();
+    assert_eq!(dvm.run(Some(3)), Ok(3));
+    assert_eq!(dvm.stack, vec![1, 2]);
+    assert_eq!(*escapes.borrow(), vec![0x3000]);
+
+    dvm.set_escape_hook(|_, _| ControlFlow::Break(()));
+
+    dvm.reset();
+    assert_eq!(dvm.run(None), Ok(1));
+    assert_eq!(dvm.pc, 0x3000);
+}
+
+#[test]
+fn restore_onto() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::const4u(0x400258), asm::deref_size(2)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.step().unwrap();
+
+    // a later core with different memory at the same address
+    let (old, _) = dvm.read_with_source(0x400258, 16).unwrap();
+    let off = core.windows(16).position(|x| x == &old[..]).unwrap();
+    let mut later = core.to_vec();
+    later[off..off + 2].copy_from_slice(&[0x34, 0x12]);
+
+    let cp = dvm.checkpoint();
+    let mut restored = DwarfVm::restore_onto(&cp, &later).unwrap();
+    assert_eq!(restored.state(), dvm.state());
+
+    dvm.step().unwrap();
+    restored.step().unwrap();
+    assert_eq!(dvm.stack, vec![0x086f]);
+    assert_eq!(restored.stack, vec![0x1234]);
+
+    dvm.pc = 0x1234;
+    assert_eq!(
+        DwarfVm::restore_onto(&dvm.checkpoint(), &later).err(),
+        Some(DwarfVmError::Unmapped { addr: 0x1234 })
+    );
+}
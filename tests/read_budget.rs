@@ -0,0 +1,54 @@
+use iris::{DwarfVm, DwarfVmError, GasTable};
+
+#[test]
+fn deref_loop() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_read_budget(10);
+
+    // DW_OP_const2u 0x2000; loop: DW_OP_deref; DW_OP_skip loop
+    dvm.overlay()
+        .insert(0x1000, vec![0x0a, 0x00, 0x20, 0x06, 0x2f, 0xfc, 0xff]);
+    dvm.overlay()
+        .insert(0x2000, 0x2000u64.to_le_bytes().to_vec());
+
+    match dvm.run(None) {
+        Err(DwarfVmError::ReadBudgetExceeded { .. }) => (),
+        r => panic!("expected read budget to trip, got {:?}", r),
+    }
+    assert_eq!(dvm.reads(), 11);
+
+    dvm.reset();
+    assert_eq!(dvm.reads(), 0);
+    assert_eq!(dvm.pc, 0x1000);
+}
+
+#[test]
+fn gas_limit() {
+    let core = include_bytes!("../res/entry2.core");
+
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_gas_limit(20);
+
+    // DW_OP_const2u 0x2000; loop: DW_OP_deref; DW_OP_skip loop
+    dvm.overlay()
+        .insert(0x1000, vec![0x0a, 0x00, 0x20, 0x06, 0x2f, 0xfc, 0xff]);
+    dvm.overlay()
+        .insert(0x2000, 0x2000u64.to_le_bytes().to_vec());
+
+    // 1 for the constant then 5 per iteration, the 4th skip runs out
+    assert_eq!(dvm.run(None), Err(DwarfVmError::OutOfGas { pc: 0x1004 }));
+    assert_eq!(dvm.gas_used(), 20);
+
+    // make derefs free
+    dvm.reset();
+    assert_eq!(dvm.gas_used(), 0);
+    dvm.set_gas_costs(GasTable {
+        memory: 0,
+        ..GasTable::default()
+    });
+
+    assert_eq!(dvm.run(None), Err(DwarfVmError::OutOfGas { pc: 0x1004 }));
+    assert_eq!(dvm.reads(), 20);
+}
@@ -1,6 +1,6 @@
 use serde::Deserialize;
 
-use iris::DwarfVm;
+use iris::{DwarfVm, ExpectedState};
 
 #[derive(Clone, Debug, Deserialize, Hash, Eq, PartialEq)]
 struct TraceState {
@@ -24,41 +24,37 @@ fn sanity() {
     let _ = dvm.step();
     let _ = dvm.step();
 
-    let mut ins = 2;
+    let states = states
+        .into_iter()
+        .map(|x| iris::TraceState {
+            pc: x.pc,
+            stack_sz: x.stack_sz,
+            stack: x.stack,
+        })
+        .collect::<Vec<_>>();
+
+    assert_eq!(states[0].diff(&dvm.state()), None);
+    assert_eq!(dvm.verify_trace(&states[1..]), Ok(()));
+}
 
-    for state in states {
-        println!(
-            "ins {}, trace pc {:x}, trace stack sz {}, trace stack {:x?}",
-            ins, state.pc, state.stack_sz, state.stack
-        );
+#[test]
+fn record_trace() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
 
-        let shortstack = dvm
-            .stack
-            .iter()
-            .rev()
-            .take(5)
-            .rev()
-            .map(|x| *x)
-            .collect::<Vec<u64>>();
-        println!(
-            "ins {}, dvm   pc {:x}, dvm   stack sz {}, dvm   stack {:x?}",
-            ins,
-            dvm.pc,
-            dvm.stack.len(),
-            shortstack
-        );
+    let states: Vec<TraceState> = serde_json::from_str(include_str!("../res/sanity-states.json"))
+        .expect("Could not deserialize state trace");
 
-        assert_eq!(state.pc, dvm.pc);
-        assert_eq!(state.stack_sz, dvm.stack.len());
-        if dvm.stack.len() > 5 {
-            assert_eq!(state.stack, shortstack);
-        } else {
-            assert_eq!(state.stack, dvm.stack);
-        }
+    // the reference trace starts at the second ins
+    let _ = dvm.step();
+    let _ = dvm.step();
 
-        println!("checked step {}...", ins);
+    let trace = dvm.record_trace(Some(100));
+    assert_eq!(trace.len(), 100);
 
-        ins += 1;
-        let _ = dvm.step();
+    for (state, recorded) in states.iter().zip(trace) {
+        assert_eq!(state.pc, recorded.pc);
+        assert_eq!(state.stack_sz, recorded.stack_sz);
+        assert_eq!(state.stack, recorded.stack);
     }
 }
@@ -0,0 +1,221 @@
+use std::convert::TryInto;
+
+use iris::{asm, DwarfVm, DwarfVmError, ReadSource, ShortReadPolicy};
+
+/// Strip the section table, leaving only the program headers
+fn segment_only(core: &[u8]) -> Vec<u8> {
+    let mut core = core.to_vec();
+
+    // e_shoff, e_shnum and e_shstrndx
+    core[0x28..0x30].copy_from_slice(&[0; 8]);
+    core[0x3c..0x40].copy_from_slice(&[0; 4]);
+
+    core
+}
+
+#[test]
+fn segment_only_core() {
+    let core = include_bytes!("../res/entry2.core");
+    let stripped = segment_only(core);
+
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+    let mut stripped_dvm = DwarfVm::new(0x400258, 0x7fffffe110, &stripped);
+
+    assert_eq!(
+        dvm.current_op_bytes().unwrap(),
+        stripped_dvm.current_op_bytes().unwrap()
+    );
+
+    dvm.run(Some(100)).unwrap();
+    stripped_dvm.run(Some(100)).unwrap();
+
+    assert_eq!(dvm.state(), stripped_dvm.state());
+}
+
+#[test]
+fn current_section() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    assert_eq!(dvm.current_section(), Some("load"));
+
+    dvm.overlay().insert(0x400258, vec![0x96]);
+    assert_eq!(dvm.current_section(), None);
+}
+
+#[test]
+fn read_with_source() {
+    let core = include_bytes!("../res/entry2.core");
+    let stripped = segment_only(core);
+
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+    let stripped_dvm = DwarfVm::new(0x400258, 0x7fffffe110, &stripped);
+
+    let (data, source) = dvm.read_with_source(0x400258, 2).unwrap();
+    assert_eq!(data, vec![0x6f, 0x08]);
+    assert_eq!(source, ReadSource::Section("load".to_string()));
+
+    let (data, source) = stripped_dvm.read_with_source(0x400258, 2).unwrap();
+    assert_eq!(data, vec![0x6f, 0x08]);
+    assert_eq!(source, ReadSource::Segment);
+
+    dvm.overlay().insert(0x400258, vec![0x96, 0x96]);
+    let (data, source) = dvm.read_with_source(0x400258, 2).unwrap();
+    assert_eq!(data, vec![0x96, 0x96]);
+    assert_eq!(source, ReadSource::Overlay);
+
+    assert!(dvm.read_with_source(0x1234, 2).is_err());
+
+    dvm.set_unmapped_fill(Some(0xaabb));
+    let (data, source) = dvm.read_with_source(0x1234, 2).unwrap();
+    assert_eq!(data, vec![0xbb, 0xaa]);
+    assert_eq!(source, ReadSource::Poison);
+}
+
+#[test]
+fn short_read_with_source() {
+    let mut dvm = DwarfVm::without_memory(0x1000, 0);
+    dvm.overlay().insert(0x2000, vec![0x96]);
+
+    assert_eq!(
+        dvm.read_with_source(0x2000, 2),
+        Err(DwarfVmError::Unmapped { addr: 0x2000 })
+    );
+
+    dvm.set_short_read_policy(ShortReadPolicy::ZeroExtend);
+    assert_eq!(
+        dvm.read_with_source(0x2000, 2),
+        Ok((vec![0x96, 0x00], ReadSource::Overlay))
+    );
+}
+
+/// Move every section and segment of the core down by `delta`
+fn unrelocate(core: &[u8], delta: u64) -> Vec<u8> {
+    let mut core = core.to_vec();
+
+    let u16_at = |core: &[u8], off: usize| u16::from_le_bytes([core[off], core[off + 1]]) as usize;
+    let u64_at = |core: &[u8], off: usize| {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&core[off..off + 8]);
+        u64::from_le_bytes(buf)
+    };
+
+    // (table offset, entry size, entry count, address field offset)
+    let tables = [
+        (
+            u64_at(&core, 0x20) as usize,
+            u16_at(&core, 0x36),
+            u16_at(&core, 0x38),
+            0x10,
+        ),
+        (
+            u64_at(&core, 0x28) as usize,
+            u16_at(&core, 0x3a),
+            u16_at(&core, 0x3c),
+            0x10,
+        ),
+    ];
+
+    for &(table, entsize, count, field) in &tables {
+        for ii in 0..count {
+            let off = table + ii * entsize + field;
+
+            let addr = u64_at(&core, off);
+            if addr != 0 {
+                core[off..off + 8].copy_from_slice(&(addr - delta).to_le_bytes());
+            }
+        }
+    }
+
+    core
+}
+
+#[test]
+fn load_bias() {
+    let core = include_bytes!("../res/entry2.core");
+    let moved = unrelocate(core, 0x10_0000);
+
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+    let mut moved_dvm = DwarfVm::new(0x400258, 0x7fffffe110, &moved);
+    moved_dvm.set_load_bias(0x10_0000);
+
+    dvm.run(Some(100)).unwrap();
+    moved_dvm.run(Some(100)).unwrap();
+
+    assert_eq!(dvm.state(), moved_dvm.state());
+}
+
+#[test]
+fn addr_bias() {
+    let mut dvm = DwarfVm::without_memory(0x1000, 0);
+    dvm.set_addr_bias(-0x1000);
+
+    // only the DW_OP_addr operand is re-based
+    let prog = [
+        asm::addr(0x402000),
+        asm::const8u(0x402000),
+        asm::lit(0),
+        asm::bra(0),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(4)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0x401000, 0x402000]);
+    assert_eq!(dvm.pc, 0x1000 + prog.concat().len() as u64);
+}
+
+#[test]
+fn section_data() {
+    let core = include_bytes!("../res/entry2.core");
+    let dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    assert!(!dvm.section_data("load").unwrap().is_empty());
+
+    assert_eq!(dvm.section_data("no such section"), None);
+    assert_eq!(dvm.section_data(""), None);
+
+    let stripped = segment_only(core);
+    let stripped_dvm = DwarfVm::new(0x400258, 0x7fffffe110, &stripped);
+    assert_eq!(stripped_dvm.section_data("load"), None);
+
+    assert_eq!(DwarfVm::without_memory(0, 0).section_data("load"), None);
+}
+
+#[test]
+fn compressed_section_not_memory() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut flagged = core.to_vec();
+
+    // set SHF_COMPRESSED on every section
+    let shoff = u64::from_le_bytes(core[0x28..0x30].try_into().unwrap()) as usize;
+    let shentsize = u16::from_le_bytes([core[0x3a], core[0x3b]]) as usize;
+    let shnum = u16::from_le_bytes([core[0x3c], core[0x3d]]) as usize;
+
+    for ii in 0..shnum {
+        // 0x800 is bit 3 of the second byte of sh_flags
+        flagged[shoff + ii * shentsize + 0x09] |= 0x08;
+    }
+
+    // reads fall back to the segments rather than the raw section bytes
+    let dvm = DwarfVm::new(0x400258, 0x7fffffe110, &flagged);
+    let (data, source) = dvm.read_with_source(0x400258, 2).unwrap();
+    assert_eq!(data, vec![0x6f, 0x08]);
+    assert_eq!(source, ReadSource::Segment);
+}
+
+#[test]
+fn section_end() {
+    let core = include_bytes!("../res/entry2.core");
+    let stripped = segment_only(core);
+
+    let dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+    let stripped_dvm = DwarfVm::new(0x400258, 0x7fffffe110, &stripped);
+
+    // 0x400000..0x415000 ends where the next section starts
+    let (data, source) = dvm.read_with_source(0x415000, 8).unwrap();
+    assert_eq!(source, ReadSource::Section("load".to_string()));
+    assert_eq!(data, stripped_dvm.read_with_source(0x415000, 8).unwrap().0);
+
+    // the end of the last section is the end of memory
+    assert!(dvm.read_with_source(0x80_0000_0000, 1).is_err());
+}
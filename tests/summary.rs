@@ -0,0 +1,177 @@
+use iris::{asm, DwarfVm, DwarfVmError, ExprResult, RunSummary, StopReason};
+
+fn vm(core: &[u8]) -> DwarfVm {
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [
+        asm::const4u(0x400258),
+        asm::deref_size(1),
+        asm::lit(1),
+        asm::plus(),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm
+}
+
+#[test]
+fn program_end() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    assert_eq!(
+        dvm.run_summary(None),
+        RunSummary {
+            instructions: 4,
+            reads: 1,
+            max_stack: 2,
+            stop_reason: StopReason::ProgramEnd,
+        }
+    );
+    assert_eq!(dvm.stack, vec![0x70]);
+}
+
+#[test]
+fn limit_and_breakpoint() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    dvm.set_breakpoint(0x1008, |_, _| true);
+
+    let summary = dvm.run_summary(Some(1));
+    assert_eq!(summary.instructions, 1);
+    assert_eq!(summary.stop_reason, StopReason::Limit);
+
+    let summary = dvm.run_summary(None);
+    assert_eq!(summary.instructions, 2);
+    assert_eq!(summary.stop_reason, StopReason::Breakpoint);
+}
+
+#[test]
+fn error() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [asm::lit(1), asm::const2u(0x1234), asm::deref_size(1)];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    let summary = dvm.run_summary(None);
+    assert_eq!(summary.instructions, 2);
+    assert_eq!(
+        summary.stop_reason,
+        StopReason::Error(DwarfVmError::Unmapped { addr: 0x1234 })
+    );
+}
+
+#[test]
+fn run_compiled() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let prog = [
+        asm::lit(3),
+        asm::lit(4),
+        asm::plus(),
+        asm::stack_value(),
+        asm::skip(1),
+        asm::nop(),
+        asm::lit(2),
+        asm::mul(),
+    ]
+    .concat();
+    let end = 0x1000 + prog.len() as u64;
+    dvm.overlay().insert(0x1000, prog);
+
+    let expr = dvm.compile(0x1000, end).unwrap();
+    assert_eq!(expr.range(), 0x1000..end);
+
+    for _ in 0..2 {
+        dvm.reset();
+        assert_eq!(dvm.run_compiled(&expr, None), Ok(7));
+        assert_eq!(dvm.pc, end);
+        assert_eq!(dvm.result(), Some(ExprResult::Value(14)));
+    }
+
+    dvm.reset();
+    assert_eq!(dvm.run_compiled(&expr, Some(2)), Ok(2));
+    assert_eq!(dvm.stack, vec![3, 4]);
+}
+
+#[test]
+fn run_compiled_like_step() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let sub = [asm::lit(2), asm::plus()].concat();
+    dvm.overlay().insert(0x2000, sub.clone());
+    dvm.add_call_target(0x2a, 0x2000, 0x2000 + sub.len() as u64);
+
+    let prog = [asm::lit(1), asm::call2(0x2a), asm::lit(5)].concat();
+    let end = 0x1000 + prog.len() as u64;
+    dvm.overlay().insert(0x1000, prog);
+
+    // calls are followed until they return
+    let expr = dvm.compile(0x1000, end).unwrap();
+    assert_eq!(dvm.run_compiled(&expr, None), Ok(5));
+    assert_eq!(dvm.pc, end);
+    assert_eq!(dvm.stack, vec![3, 5]);
+
+    // resuming from a breakpoint steps over it once, then it hits again
+    dvm.set_breakpoint(0x1004, |_, _| true);
+    dvm.reset();
+    assert_eq!(dvm.run(None), Ok(4));
+    assert_eq!(dvm.run_compiled(&expr, None), Ok(1));
+    assert_eq!(dvm.stack, vec![3, 5]);
+
+    dvm.pc = 0x1004;
+    assert_eq!(dvm.step(), Err(DwarfVmError::Breakpoint { pc: 0x1004 }));
+}
+
+#[test]
+fn check_balanced() {
+    let mut dvm = DwarfVm::without_memory(0x1000, 0);
+
+    let prog = [asm::lit(1), asm::lit(2), asm::plus()];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(
+        dvm.check_balanced(),
+        Err(DwarfVmError::UnbalancedStack { remaining: 2 })
+    );
+
+    dvm.run(Some(1)).unwrap();
+    assert_eq!(dvm.check_balanced(), Ok(()));
+
+    dvm.set_stack(Vec::new());
+    assert_eq!(
+        dvm.check_balanced(),
+        Err(DwarfVmError::UnbalancedStack { remaining: 0 })
+    );
+}
+
+#[test]
+fn max_stack_depth() {
+    let mut dvm = DwarfVm::without_memory(0x1000, 0);
+
+    let prog = [
+        asm::lit(1),
+        asm::lit(2),
+        asm::lit(3),
+        asm::plus(),
+        asm::plus(),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    assert_eq!(dvm.max_stack_depth(), 0);
+
+    dvm.run(Some(5)).unwrap();
+    assert_eq!(dvm.stack, vec![6]);
+    assert_eq!(dvm.max_stack_depth(), 3);
+
+    // the high-water mark starts over on reset
+    dvm.reset();
+    assert_eq!(dvm.max_stack_depth(), 0);
+
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.max_stack_depth(), 2);
+}
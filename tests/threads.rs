@@ -0,0 +1,252 @@
+use iris::{asm, DwarfVm, DwarfVmError, RegSource};
+
+#[test]
+fn threads() {
+    let core = include_bytes!("../res/entry2.core");
+    let dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    let threads = dvm.threads();
+    assert_eq!(threads.len(), 1);
+    assert_eq!(threads[0].pid, 18160);
+
+    // x25 holds the context pointer passed to execute_stack_op
+    assert_eq!(threads[0].dwarf_reg(25), Some(0x7fffffe110));
+    assert_eq!(threads[0].regs()[32], 0x7fb7d6cbd8);
+}
+
+#[test]
+fn notes() {
+    let core = include_bytes!("../res/entry2.core");
+    let dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    // NT_PRSTATUS
+    let prstatus = dvm
+        .notes()
+        .filter(|&(ty, name, _)| ty == 1 && name == "CORE")
+        .count();
+
+    assert_eq!(prstatus, dvm.threads().len());
+}
+
+#[test]
+fn from_thread() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::from_thread(0x1000, core, 0).unwrap();
+
+    // DW_OP_reg25
+    dvm.overlay().insert(0x1000, vec![0x50 + 25]);
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.stack, vec![0x7fffffe110]);
+}
+
+#[test]
+fn breg_deref() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::from_thread(0x1000, core, 0).unwrap();
+
+    // x25 points at the context, read 4 bytes 8 after it and 8 before it
+    let prog = [
+        asm::bregx(25, 8),
+        asm::deref_size(4),
+        asm::breg(25, -8),
+        asm::deref_size(4),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(4)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0xffffe508, 0xfffff3e0]);
+}
+
+#[test]
+fn register_count() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::from_thread(0x1000, core, 0).unwrap();
+    dvm.set_register_count(32);
+
+    let prog = [asm::reg(25), asm::regx(40)];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::BadRegister { reg: 40 }));
+}
+
+#[test]
+fn register_overflow() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, u64::MAX - 8, core);
+
+    dvm.overlay().insert(0x1000, asm::regx(2));
+    assert_eq!(dvm.step(), Err(DwarfVmError::BadRegister { reg: 2 }));
+
+    // the multiply overflows even with a sane context
+    dvm.set_ctx(0x7fffffe110);
+    dvm.overlay().insert(0x1000, asm::regx(1 << 61));
+    dvm.reset();
+    assert_eq!(dvm.step(), Err(DwarfVmError::BadRegister { reg: 1 << 61 }));
+}
+
+#[test]
+fn select_thread() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    dvm.overlay().insert(0x1000, asm::reg(25));
+
+    assert_eq!(
+        dvm.select_thread(1),
+        Err(DwarfVmError::NoSuchThread { idx: 1 })
+    );
+
+    dvm.select_thread(0).unwrap();
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.stack, vec![0x7fffffe110]);
+}
+
+#[test]
+fn new_checked() {
+    let core = include_bytes!("../res/entry2.core");
+
+    assert!(DwarfVm::new_checked(0x400258, 0x7fffffe110, core).is_ok());
+    assert_eq!(
+        DwarfVm::new_checked(0x400258, 0x1234, core).err(),
+        Some(DwarfVmError::Unmapped { addr: 0x1234 })
+    );
+}
+
+#[test]
+fn form_tls_address() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit8; DW_OP_form_tls_address
+    dvm.overlay().insert(0x1000, vec![0x38, 0x9b]);
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::NoTlsBase { pc: 0x1001 }));
+
+    dvm.set_tls_base(0x7fb7ff0000);
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.stack, vec![0x7fb7ff0008]);
+}
+
+#[test]
+fn gnu_push_tls_address() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_tls_base(0x7fb7ff0000);
+
+    // DW_OP_lit8; DW_OP_GNU_push_tls_address
+    dvm.overlay().insert(0x1000, vec![0x38, 0xe0]);
+
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0x7fb7ff0008]);
+}
+
+#[test]
+fn validate_ctx() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x400258, 0x7fffffe110, core);
+
+    dvm.validate_ctx(32).unwrap();
+
+    dvm.set_ctx(0x1234);
+    assert_eq!(
+        dvm.validate_ctx(32),
+        Err(DwarfVmError::BadContext {
+            ctx: 0x1234,
+            addr: 0x1234
+        })
+    );
+
+    // only the start of the register array is in the overlay
+    dvm.set_ctx(0x2000);
+    dvm.overlay().insert(0x2000, vec![0; 16]);
+    dvm.validate_ctx(2).unwrap();
+    assert_eq!(
+        dvm.validate_ctx(32),
+        Err(DwarfVmError::BadContext {
+            ctx: 0x2000,
+            addr: 0x2010
+        })
+    );
+
+    // the register array would wrap around the address space
+    dvm.set_ctx(u64::MAX - 8);
+    dvm.overlay().insert(u64::MAX - 8, vec![0; 9]);
+    assert_eq!(
+        dvm.validate_ctx(2),
+        Err(DwarfVmError::BadContext {
+            ctx: u64::MAX - 8,
+            addr: u64::MAX
+        })
+    );
+    assert!(dvm.validate_ctx(usize::MAX).is_err());
+}
+
+#[test]
+fn overlay_ctx() {
+    let mut dvm = DwarfVm::without_memory(0x1000, 0x3000);
+
+    // the context holds pointers to the saved registers
+    let mut ctx = Vec::new();
+    for r in 0..8u64 {
+        ctx.extend_from_slice(&(0x4000 + r * 8).to_le_bytes());
+    }
+    dvm.overlay().insert(0x3000, ctx);
+
+    let mut regs = Vec::new();
+    for r in 0..8u64 {
+        regs.extend_from_slice(&(0x100 + r).to_le_bytes());
+    }
+    dvm.overlay().insert(0x4000, regs);
+
+    let prog = [asm::reg(6), asm::breg(7, -7)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0x106, 0x100]);
+}
+
+#[test]
+fn effective_reg() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0x3000, core);
+
+    // the context holds a pointer to x6
+    dvm.overlay()
+        .insert(0x3030, 0x4000u64.to_le_bytes().to_vec());
+    dvm.overlay().insert(0x4000, 0x66u64.to_le_bytes().to_vec());
+
+    assert_eq!(dvm.effective_reg(6), Ok((0x66, RegSource::Context)));
+
+    // inspecting isn't a read by the expression
+    dvm.set_read_budget(0);
+    assert_eq!(dvm.effective_reg(6), Ok((0x66, RegSource::Context)));
+    assert_eq!(dvm.reads(), 0);
+
+    dvm.set_register_provider(|r| Ok(r + 0x100));
+    assert_eq!(dvm.effective_reg(6), Ok((0x106, RegSource::Provider)));
+
+    dvm.overlay().insert(0x1000, asm::reg(6));
+    dvm.step().unwrap();
+    assert_eq!(dvm.stack, vec![0x106]);
+}
+
+#[test]
+fn breg_negative_offset() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    let rbp = 0x7fff_ffff_e450;
+    dvm.set_register_provider(move |_| Ok(rbp));
+
+    let prog = [asm::breg(6, -16), asm::bregx(6, -0x1000), asm::breg(6, 16)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.run(Some(3)).unwrap();
+
+    assert_eq!(dvm.stack, vec![rbp - 16, rbp - 0x1000, rbp + 16]);
+}
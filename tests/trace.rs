@@ -0,0 +1,248 @@
+use iris::{asm, DwarfVm, DwarfVmError, DwarfVmState, StateDiff, TraceMismatch};
+
+fn vm(core: &[u8]) -> DwarfVm {
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_lit1; DW_OP_lit2; DW_OP_plus
+    dvm.overlay().insert(0x1000, vec![0x31, 0x32, 0x22]);
+
+    dvm
+}
+
+#[test]
+fn verify_trace() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    let states = vec![
+        DwarfVmState::new(0x1001, vec![1]),
+        DwarfVmState::new(0x1002, vec![1, 2]),
+        DwarfVmState::new(0x1003, vec![3]),
+    ];
+
+    assert_eq!(dvm.verify_trace(&states), Ok(()));
+}
+
+#[test]
+fn verify_trace_mismatch() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    let states = vec![
+        DwarfVmState::new(0x1001, vec![1]),
+        DwarfVmState::new(0x1002, vec![1, 3]),
+    ];
+
+    assert_eq!(
+        dvm.verify_trace(&states),
+        Err(TraceMismatch {
+            index: 1,
+            diff: StateDiff::Stack {
+                idx: 1,
+                expected: 3,
+                actual: 2
+            },
+        })
+    );
+}
+
+#[test]
+fn edge_trace() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.enable_edge_trace();
+
+    // DW_OP_lit0; DW_OP_bra +1; DW_OP_lit1; DW_OP_bra +1; DW_OP_nop; DW_OP_skip 0
+    dvm.overlay().insert(
+        0x1000,
+        vec![
+            0x30, 0x28, 0x01, 0x00, 0x31, 0x28, 0x01, 0x00, 0x96, 0x2f, 0x00, 0x00,
+        ],
+    );
+
+    dvm.run(Some(5)).unwrap();
+
+    assert_eq!(
+        dvm.edge_trace(),
+        &[
+            (0x1001, 0x1004, false),
+            (0x1005, 0x1009, true),
+            (0x1009, 0x100c, true)
+        ]
+    );
+}
+
+#[test]
+fn top_state() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    dvm.step().unwrap();
+    dvm.step().unwrap();
+
+    let state = dvm.top_state(1);
+    assert_eq!(state.pc(), 0x1002);
+    assert_eq!(state.stack(), &[2]);
+
+    assert_eq!(dvm.top_state(5).stack(), &[1, 2]);
+}
+
+#[test]
+fn pick_abs() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    dvm.step().unwrap();
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.pick_abs(0), Some(1));
+    assert_eq!(dvm.pick_abs(1), Some(2));
+    assert_eq!(dvm.pick_abs(2), None);
+}
+
+#[test]
+fn state_order() {
+    let mut states = vec![
+        DwarfVmState::new(0x1002, vec![1]),
+        DwarfVmState::new(0x1001, vec![2]),
+        DwarfVmState::new(0x1001, vec![1, 5]),
+    ];
+    states.sort();
+
+    assert_eq!(
+        states,
+        vec![
+            DwarfVmState::new(0x1001, vec![1, 5]),
+            DwarfVmState::new(0x1001, vec![2]),
+            DwarfVmState::new(0x1002, vec![1]),
+        ]
+    );
+}
+
+#[test]
+fn set_stack() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_bra +1; DW_OP_nop; DW_OP_nop
+    dvm.overlay()
+        .insert(0x1000, vec![0x28, 0x01, 0x00, 0x96, 0x96]);
+
+    dvm.set_stack(vec![5, 0]);
+    dvm.step().unwrap();
+    assert_eq!(dvm.pc, 0x1003);
+
+    dvm.pc = 0x1000;
+    dvm.set_stack(vec![5, 1]);
+    dvm.step().unwrap();
+    assert_eq!(dvm.pc, 0x1004);
+    assert_eq!(dvm.stack, vec![5]);
+}
+
+#[test]
+fn find_step() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    let top_is = |v| move |dvm: &DwarfVm| dvm.stack.last() == Some(&v);
+
+    assert_eq!(dvm.find_step(top_is(2), None), Ok(Some(0x1002)));
+    assert_eq!(dvm.find_step(top_is(3), Some(1)), Ok(Some(0x1003)));
+
+    dvm.reset();
+    assert_eq!(dvm.find_step(top_is(3), Some(2)), Ok(None));
+}
+
+#[test]
+fn stack_capacity() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core).with_stack_capacity(1024);
+    assert!(dvm.stack.capacity() >= 1024);
+
+    dvm.set_state(&DwarfVmState::new(0x1000, vec![1, 2, 3]));
+    assert_eq!(dvm.stack, vec![1, 2, 3]);
+    assert!(dvm.stack.capacity() >= 1024);
+
+    dvm.set_stack(vec![4, 5]);
+    assert_eq!(dvm.stack, vec![4, 5]);
+    assert!(dvm.stack.capacity() >= 1024);
+
+    dvm.reset();
+    assert!(dvm.stack.capacity() >= 1024);
+}
+
+#[test]
+fn stack_no_realloc() {
+    // a typical expression fits the reservation
+    let mut dvm = DwarfVm::without_memory(0x1000, 0).with_stack_capacity(16);
+    let prog = [asm::lit(1), asm::lit(2), asm::plus()].concat();
+    dvm.overlay().insert(0x1000, prog);
+
+    let buf = dvm.stack.as_ptr();
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![3]);
+    assert_eq!(dvm.stack.as_ptr(), buf);
+
+    // as does an unrolled one pushing thousands of values
+    let mut dvm = DwarfVm::without_memory(0x1000, 0).with_stack_capacity(4096);
+    dvm.overlay().insert(0x1000, asm::lit(1).repeat(4000));
+
+    let buf = dvm.stack.as_ptr();
+    dvm.run(Some(4000)).unwrap();
+    assert_eq!(dvm.stack.len(), 4000);
+    assert_eq!(dvm.stack.as_ptr(), buf);
+}
+
+#[test]
+fn replay_edges() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.enable_edge_trace();
+
+    // DW_OP_lit0; DW_OP_bra +1; DW_OP_lit1; DW_OP_bra +1; DW_OP_nop; DW_OP_skip 0
+    dvm.overlay().insert(
+        0x1000,
+        vec![
+            0x30, 0x28, 0x01, 0x00, 0x31, 0x28, 0x01, 0x00, 0x96, 0x2f, 0x00, 0x00,
+        ],
+    );
+
+    dvm.run(Some(5)).unwrap();
+    let edges = dvm.edge_trace().to_vec();
+
+    dvm.reset();
+    assert_eq!(dvm.replay_edges(&edges), Ok(()));
+    assert_eq!(dvm.pc, 0x100c);
+
+    // take the first branch this time
+    dvm.overlay().get_mut(&0x1000).unwrap()[0] = 0x31;
+    dvm.reset();
+    assert_eq!(
+        dvm.replay_edges(&edges),
+        Err(DwarfVmError::TraceDivergence { index: 0 })
+    );
+}
+
+#[test]
+fn run_until_depth() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = vm(core);
+
+    assert_eq!(dvm.run_until_depth(2, None), Ok(2));
+    assert_eq!(dvm.pc, 0x1002);
+    assert_eq!(dvm.stack, vec![1, 2]);
+
+    // already at the depth
+    assert_eq!(dvm.run_until_depth(2, None), Ok(0));
+
+    // the stack never gets this deep, so the VM runs off the end
+    assert_eq!(
+        dvm.run_until_depth(3, None),
+        Err(DwarfVmError::Unmapped { addr: 0x1003 })
+    );
+    assert_eq!(dvm.stack, vec![3]);
+
+    dvm.reset();
+    assert_eq!(dvm.run_until_depth(3, Some(2)), Ok(2));
+    assert_eq!(dvm.pc, 0x1002);
+}
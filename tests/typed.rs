@@ -0,0 +1,64 @@
+use iris::{asm, DwarfVm, DwarfVmError};
+
+#[test]
+fn const_type_4() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_const_type <0x2a> 4 [78 56 34 12]
+    dvm.overlay()
+        .insert(0x1000, vec![0xa4, 0x2a, 0x04, 0x78, 0x56, 0x34, 0x12]);
+
+    dvm.step().unwrap();
+
+    assert_eq!(dvm.pc, 0x1007);
+    assert_eq!(dvm.stack, vec![0x1234_5678]);
+}
+
+#[test]
+fn convert() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.add_base_type(0x30, 4, true);
+    dvm.add_base_type(0x40, 8, false);
+    dvm.add_base_type(0x50, 4, false);
+
+    let prog = [
+        asm::const8u(0x1234_5678_8000_0001),
+        asm::convert(0x30),
+        asm::convert(0x40),
+        asm::dup(),
+        asm::convert(0x50),
+        asm::convert(0x60),
+    ];
+    dvm.overlay().insert(0x1000, prog.concat());
+
+    // down to signed 32-bit, then back up with sign extension
+    dvm.run(Some(3)).unwrap();
+    assert_eq!(dvm.stack, vec![0xffff_ffff_8000_0001]);
+
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.stack, vec![0xffff_ffff_8000_0001, 0x8000_0001]);
+
+    assert_eq!(
+        dvm.step(),
+        Err(DwarfVmError::UnsupportedTypedOp { pc: 0x1010 })
+    );
+}
+
+#[test]
+fn convert_generic() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.add_base_type(0x30, 2, true);
+
+    let prog = [asm::const2u(0xfffe), asm::convert(0x30), asm::convert(0)];
+    dvm.overlay().insert(0x1000, prog.concat());
+    dvm.set_addr_size(4);
+
+    dvm.run(Some(2)).unwrap();
+    assert_eq!(dvm.stack, vec![0xffff_fffe]);
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.stack, vec![0xffff_fffe]);
+}
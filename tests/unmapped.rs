@@ -0,0 +1,76 @@
+use iris::{DwarfVm, DwarfVmError, ShortReadPolicy, DETERMINISTIC_FILL};
+
+#[test]
+fn unmapped_fill() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_const2u 0x1234; DW_OP_deref
+    dvm.overlay().insert(0x1000, vec![0x0a, 0x34, 0x12, 0x06]);
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::Unmapped { addr: 0x1234 }));
+
+    dvm.reset();
+    dvm.set_unmapped_fill(Some(0xdeadbeefdeadbeef));
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0xdeadbeefdeadbeef]);
+}
+
+#[test]
+fn ptr_auth_mask() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_ptr_auth_mask(0xffff_0000_0000_0000);
+
+    // DW_OP_const8u 0x002a_0000_0000_2000; DW_OP_deref
+    let mut prog = vec![0x0e];
+    prog.extend_from_slice(&0x002a_0000_0000_2000u64.to_le_bytes());
+    prog.push(0x06);
+
+    dvm.overlay().insert(0x1000, prog);
+    dvm.overlay().insert(0x2000, 0x42u64.to_le_bytes().to_vec());
+
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0x42]);
+}
+
+#[test]
+fn short_read_zero_extend() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+
+    // DW_OP_const2u 0x2000; DW_OP_deref
+    dvm.overlay().insert(0x1000, vec![0x0a, 0x00, 0x20, 0x06]);
+    dvm.overlay().insert(0x2000, vec![0x34, 0x12]);
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::Unmapped { addr: 0x2000 }));
+
+    dvm.reset();
+    dvm.set_short_read_policy(ShortReadPolicy::ZeroExtend);
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.stack, vec![0x1234]);
+}
+
+#[test]
+fn deterministic() {
+    let core = include_bytes!("../res/entry2.core");
+    let mut dvm = DwarfVm::new(0x1000, 0, core);
+    dvm.set_deterministic(true);
+
+    // DW_OP_const2u 0x1234; DW_OP_deref
+    dvm.overlay().insert(0x1000, vec![0x0a, 0x34, 0x12, 0x06]);
+
+    dvm.step().unwrap();
+    assert_eq!(dvm.step(), Err(DwarfVmError::Unmapped { addr: 0x1234 }));
+
+    dvm.reset();
+    dvm.set_unmapped_fill(Some(0x4141));
+    dvm.run(Some(2)).unwrap();
+
+    assert_eq!(dvm.stack, vec![DETERMINISTIC_FILL]);
+}